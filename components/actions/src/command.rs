@@ -0,0 +1,112 @@
+use std::{fmt, future::Future};
+
+pub use logit::WorkflowCommand;
+
+/// Where an annotation points to, shown next to the message on the run page.
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationProperties {
+    pub title: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub end_line: Option<usize>,
+    pub col: Option<usize>,
+    pub end_column: Option<usize>,
+}
+
+impl AnnotationProperties {
+    pub fn title(title: impl Into<String>) -> Self {
+        Self {
+            title: Some(title.into()),
+            ..Self::default()
+        }
+    }
+
+    fn into_properties(self) -> Vec<(&'static str, String)> {
+        [
+            ("title", self.title),
+            ("file", self.file),
+            ("line", self.line.map(|x| x.to_string())),
+            ("endLine", self.end_line.map(|x| x.to_string())),
+            ("col", self.col.map(|x| x.to_string())),
+            ("endColumn", self.end_column.map(|x| x.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| v.map(|v| (k, v)))
+        .collect()
+    }
+}
+
+fn annotation(command: &'static str, message: impl fmt::Display, props: AnnotationProperties) {
+    props
+        .into_properties()
+        .into_iter()
+        .fold(
+            WorkflowCommand::new(command, message),
+            |cmd, (key, value)| cmd.property(key, value),
+        )
+        .issue();
+}
+
+/// Creates an error annotation.
+pub fn error(message: impl fmt::Display, props: AnnotationProperties) {
+    annotation("error", message, props)
+}
+
+/// Creates a warning annotation.
+pub fn warning(message: impl fmt::Display, props: AnnotationProperties) {
+    annotation("warning", message, props)
+}
+
+/// Creates a notice annotation.
+pub fn notice(message: impl fmt::Display, props: AnnotationProperties) {
+    annotation("notice", message, props)
+}
+
+/// Writes a debug message, only shown when step debug logging is enabled.
+pub fn debug(message: impl fmt::Display) {
+    WorkflowCommand::new("debug", message).issue()
+}
+
+/// Masks `secret` in the logs of all the following steps.
+pub fn add_mask(secret: impl fmt::Display) {
+    WorkflowCommand::new("add-mask", secret).issue()
+}
+
+/// Begins an expandable group in the log, until `end_group`.
+pub fn start_group(name: impl fmt::Display) {
+    WorkflowCommand::new("group", name).issue()
+}
+
+pub fn end_group() {
+    WorkflowCommand::new("endgroup", "").issue()
+}
+
+/// Folds everything logged while `fut` runs into an expandable group.
+pub async fn group<F: Future>(name: impl fmt::Display, fut: F) -> F::Output {
+    start_group(name);
+    let output = fut.await;
+    end_group();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnnotationProperties;
+
+    #[test]
+    fn test_annotation_properties() {
+        let props = AnnotationProperties {
+            file: Some("a.rs".to_string()),
+            end_line: Some(3),
+            ..AnnotationProperties::title("cherry-pick")
+        };
+        assert_eq!(
+            props.into_properties(),
+            vec![
+                ("title", "cherry-pick".to_string()),
+                ("file", "a.rs".to_string()),
+                ("endLine", "3".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use std::{env, fmt, fs, io, path::PathBuf};
+
+use octocrab::models::events::payload::EventPayload;
+use serde::{Deserialize, Serialize};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ContextError {
+    #[error("environment variable {0} is not set")]
+    MissingVar(&'static str),
+    #[error("invalid value {value:?} of environment variable {name}")]
+    InvalidVar { name: &'static str, value: String },
+    #[error("read github event file {path}")]
+    ReadEvent {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("deserialize github event file {path}")]
+    ParseEvent {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Context {
+    /**
+     * Webhook payload object that triggered the workflow, `UnknownEvent` if octocrab
+     * can't model it
+     */
+    pub payload: EventPayload,
+
+    /**
+     * The payload as read, with the fields octocrab drops, e.g. the `label` of
+     * `labeled` pull request events
+     */
+    pub raw_payload: serde_json::Value,
+
+    /**
+     * Name of the webhook event that triggered the workflow, e.g. `issue_comment`
+     */
+    pub event_name: String,
+    pub sha: String,
+    pub r#ref: String,
+    pub workflow: String,
+    pub action: String,
+    pub actor: String,
+    pub job: String,
+    pub run_number: usize,
+    pub run_id: usize,
+    pub api_url: String,
+    pub server_url: String,
+    pub graphql_url: String,
+    /**
+     * Repository the workflow runs in, as `owner/repo`
+     */
+    pub repository: String,
+    pub repository_owner: String,
+    /**
+     * Base and head branches of the pull request, only set for pull request events
+     */
+    pub base_ref: Option<String>,
+    pub head_ref: Option<String>,
+    pub runner_temp: PathBuf,
+    pub workspace: PathBuf,
+}
+
+/// The repository the workflow runs in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RepoRef {
+    pub owner: String,
+    pub repo: String,
+}
+
+impl fmt::Display for RepoRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.repo)
+    }
+}
+
+/// The issue or pull request that triggered the workflow.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IssueRef {
+    pub owner: String,
+    pub repo: String,
+    pub number: u64,
+}
+
+fn var(name: &'static str) -> Result<String, ContextError> {
+    env::var(name).map_err(|_| ContextError::MissingVar(name))
+}
+
+fn var_or(name: &'static str, default: &str) -> String {
+    env::var(name)
+        .ok()
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+fn opt_var(name: &'static str) -> Option<String> {
+    env::var(name).ok().filter(|x| !x.is_empty())
+}
+
+fn parse_var(name: &'static str) -> Result<usize, ContextError> {
+    let value = var(name)?;
+    value
+        .parse()
+        .map_err(|_| ContextError::InvalidVar { name, value })
+}
+
+impl Context {
+    /// Reads the context from the environment variables set by the runner.
+    pub fn from_env() -> Result<Self, ContextError> {
+        let path = var("GITHUB_EVENT_PATH")?;
+        let f = fs::File::open(&path).map_err(|source| ContextError::ReadEvent {
+            path: path.clone(),
+            source,
+        })?;
+        let raw_payload: serde_json::Value = serde_json::from_reader(io::BufReader::new(f))
+            .map_err(|source| ContextError::ParseEvent { path, source })?;
+        let payload = serde_json::from_value(raw_payload.clone())
+            .unwrap_or_else(|_| EventPayload::UnknownEvent(Box::new(raw_payload.clone())));
+
+        Ok(Self {
+            payload,
+            raw_payload,
+            event_name: var("GITHUB_EVENT_NAME")?,
+            sha: var("GITHUB_SHA")?,
+            r#ref: var("GITHUB_REF")?,
+            workflow: var("GITHUB_WORKFLOW")?,
+            action: var("GITHUB_ACTION")?,
+            actor: var("GITHUB_ACTOR")?,
+            job: var("GITHUB_JOB")?,
+            run_number: parse_var("GITHUB_RUN_NUMBER")?,
+            run_id: parse_var("GITHUB_RUN_ID")?,
+            api_url: var_or("GITHUB_API_URL", "https://api.github.com"),
+            server_url: var_or("GITHUB_SERVER_URL", "https://github.com"),
+            graphql_url: var_or("GITHUB_GRAPHQL_URL", "https://api.github.com/graphql"),
+            repository: var("GITHUB_REPOSITORY")?,
+            repository_owner: var("GITHUB_REPOSITORY_OWNER")?,
+            base_ref: opt_var("GITHUB_BASE_REF"),
+            head_ref: opt_var("GITHUB_HEAD_REF"),
+            runner_temp: var("RUNNER_TEMP")?.into(),
+            workspace: var("GITHUB_WORKSPACE")?.into(),
+        })
+    }
+
+    /// Returns the repository the workflow runs in.
+    pub fn repo(&self) -> RepoRef {
+        let (owner, repo) = self
+            .repository
+            .split_once('/')
+            .unwrap_or((&self.repository_owner, &self.repository));
+        RepoRef {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        }
+    }
+
+    /// Returns the issue or pull request the payload refers to, if any.
+    pub fn issue(&self) -> Option<IssueRef> {
+        let number = match &self.payload {
+            EventPayload::IssuesEvent(evt) => evt.issue.number,
+            EventPayload::IssueCommentEvent(evt) => evt.issue.number,
+            EventPayload::PullRequestEvent(evt) => evt.number,
+            EventPayload::UnknownEvent(value) => {
+                ["/issue/number", "/pull_request/number", "/number"]
+                    .iter()
+                    .find_map(|p| value.pointer(p).and_then(|x| x.as_u64()))?
+            }
+            _ => return None,
+        };
+        let RepoRef { owner, repo } = self.repo();
+        Some(IssueRef {
+            owner,
+            repo,
+            number,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(repository: &str, payload: serde_json::Value) -> Context {
+        Context {
+            payload: EventPayload::UnknownEvent(Box::new(payload.clone())),
+            raw_payload: payload,
+            event_name: "workflow_dispatch".to_string(),
+            sha: String::new(),
+            r#ref: String::new(),
+            workflow: String::new(),
+            action: String::new(),
+            actor: String::new(),
+            job: String::new(),
+            run_number: 1,
+            run_id: 1,
+            api_url: String::new(),
+            server_url: String::new(),
+            graphql_url: String::new(),
+            repository: repository.to_string(),
+            repository_owner: "owner".to_string(),
+            base_ref: None,
+            head_ref: None,
+            runner_temp: PathBuf::new(),
+            workspace: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn test_repo_and_issue() {
+        let ctx = context("0x5459/cherrypicker-action", serde_json::json!({}));
+        assert_eq!(
+            ctx.repo(),
+            RepoRef {
+                owner: "0x5459".to_string(),
+                repo: "cherrypicker-action".to_string(),
+            }
+        );
+        assert_eq!(ctx.repo().to_string(), "0x5459/cherrypicker-action");
+        assert_eq!(ctx.issue(), None);
+
+        let cases = vec![
+            serde_json::json!({ "issue": { "number": 1 } }),
+            serde_json::json!({ "pull_request": { "number": 1 } }),
+            serde_json::json!({ "number": 1 }),
+        ];
+        for payload in cases {
+            let ctx = context("0x5459/cherrypicker-action", payload.clone());
+            assert_eq!(
+                ctx.issue(),
+                Some(IssueRef {
+                    owner: "0x5459".to_string(),
+                    repo: "cherrypicker-action".to_string(),
+                    number: 1,
+                }),
+                "payload: {}",
+                payload
+            );
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    env,
+    fmt::Display,
+    fs::OpenOptions,
+    io::{self, Write},
+    iter,
+    path::Path,
+};
+
+use uuid::Uuid;
+
+/// Sets the output `name` of the current step, which later steps read as
+/// `steps.<id>.outputs.<name>`.
+pub fn set_output(name: impl AsRef<str>, value: impl Display) -> io::Result<()> {
+    issue_file_command(
+        "OUTPUT",
+        prepare_key_value_message(name.as_ref(), &value.to_string())?,
+    )
+}
+
+/// Sets the environment variable `name` for this step and all the following ones.
+pub fn export_variable(name: impl AsRef<str>, value: impl Display) -> io::Result<()> {
+    let value = value.to_string();
+    env::set_var(name.as_ref(), &value);
+    issue_file_command("ENV", prepare_key_value_message(name.as_ref(), &value)?)
+}
+
+/// Prepends `path` to `PATH` for this step and all the following ones.
+pub fn add_path(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let paths = env::var_os("PATH").unwrap_or_default();
+    let joined = env::join_paths(iter::once(path.to_path_buf()).chain(env::split_paths(&paths)))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    env::set_var("PATH", joined);
+    issue_file_command("PATH", path.display())
+}
+
+/// Appends `message` to the file of the given file command, e.g. `$GITHUB_OUTPUT`.
+pub(crate) fn issue_file_command(command: &str, message: impl Display) -> io::Result<()> {
+    let var = format!("GITHUB_{}", command);
+    let path = env::var_os(&var).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "unable to find environment variable for file command {}",
+                var
+            ),
+        )
+    })?;
+
+    // The runner creates the file, a missing one means we are not running in a step.
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    writeln!(file, "{}", message)
+}
+
+/// Formats `key` and `value` as a heredoc, so that multiline values are preserved.
+fn prepare_key_value_message(key: &str, value: &str) -> io::Result<String> {
+    let delimiter = format!("ghadelimiter_{}", Uuid::new_v4());
+    if key.contains(&delimiter) || value.contains(&delimiter) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "unexpected input: name or value should not contain the delimiter {}",
+                delimiter
+            ),
+        ));
+    }
+    Ok(format!("{}<<{}\n{}\n{}", key, delimiter, value, delimiter))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Points the file command `command` at a new temporary file.
+    fn command_file(command: &str) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        env::set_var(format!("GITHUB_{}", command), file.path());
+        file
+    }
+
+    /// Reads back the `key<<delimiter` heredocs of a file command, checking the delimiters.
+    fn read_key_values(file: &tempfile::NamedTempFile) -> Vec<(String, String)> {
+        let text = fs::read_to_string(file.path()).unwrap();
+        let mut lines = text.lines();
+        let mut key_values = Vec::new();
+        while let Some(line) = lines.next() {
+            let (key, delimiter) = line.split_once("<<").unwrap();
+            assert!(delimiter.starts_with("ghadelimiter_"), "{}", delimiter);
+            let value = lines
+                .by_ref()
+                .take_while(|line| *line != delimiter)
+                .collect::<Vec<_>>()
+                .join("\n");
+            key_values.push((key.to_string(), value));
+        }
+        key_values
+    }
+
+    #[test]
+    fn test_set_output() {
+        let file = command_file("OUTPUT");
+        let cases = vec![
+            ("backport-numbers", "[12,34]"),
+            ("failed-branches", "[]"),
+            ("body", "line 1\nline 2\n\nline 4"),
+            ("empty", ""),
+        ];
+        for (name, value) in &cases {
+            set_output(name, value).unwrap();
+        }
+
+        let expected = cases
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(read_key_values(&file), expected);
+    }
+
+    #[test]
+    fn test_export_variable() {
+        let file = command_file("ENV");
+        let cases = vec![
+            ("TEST_EXPORT_NUMBER", "12"),
+            ("TEST_EXPORT_MULTILINE", "a\nb"),
+        ];
+        for (name, value) in &cases {
+            export_variable(name, value).unwrap();
+            assert_eq!(env::var(name).as_deref(), Ok(*value), "{}", name);
+        }
+
+        let expected = cases
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(read_key_values(&file), expected);
+    }
+
+    #[test]
+    fn test_add_path() {
+        let file = command_file("PATH");
+        let dir = tempfile::tempdir().unwrap();
+        add_path(dir.path()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            format!("{}\n", dir.path().display())
+        );
+        let paths = env::var_os("PATH").unwrap();
+        assert_eq!(env::split_paths(&paths).next().as_deref(), Some(dir.path()));
+    }
+
+    #[test]
+    fn test_prepare_key_value_message() {
+        let message = prepare_key_value_message("name", "a\nb").unwrap();
+        let (head, rest) = message.split_once('\n').unwrap();
+        let delimiter = head.strip_prefix("name<<").unwrap();
+        assert_eq!(rest, format!("a\nb\n{}", delimiter));
+        // Every message has its own delimiter.
+        assert_ne!(prepare_key_value_message("name", "a\nb").unwrap(), message);
+    }
+
+    #[test]
+    fn test_issue_file_command_missing() {
+        let error = issue_file_command("TEST_MISSING", "x").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
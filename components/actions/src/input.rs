@@ -0,0 +1,165 @@
+use std::{env, fmt::Display, str::FromStr};
+
+#[derive(thiserror::Error, Debug)]
+pub enum InputError {
+    #[error("Input required and not supplied: {0}")]
+    Required(String),
+    #[error("Input does not meet YAML 1.2 \"Core Schema\" specification: {0}\nSupport boolean input list: `true | True | TRUE | false | False | FALSE`")]
+    InvalidBoolean(String),
+    #[error("Invalid value {value:?} for input {name}: {reason}")]
+    Invalid {
+        name: String,
+        value: String,
+        reason: String,
+    },
+}
+
+/// Returns the trimmed value of the input `name`, or None if it is not set.
+///
+/// The runner exposes inputs as `INPUT_<NAME>` environment variables, with spaces
+/// replaced by underscores and hyphens kept. As hyphens cannot be used in variables of
+/// most shells, `INPUT_<NAME>` with hyphens replaced by underscores is looked up too.
+fn get_raw_input(name: &str) -> Option<String> {
+    let k = format!("INPUT_{}", name.replace(' ', "_").to_uppercase());
+    env::var(&k)
+        .or_else(|_| env::var(k.replace('-', "_")))
+        .ok()
+        .map(|x| x.trim().to_string())
+}
+
+/// Returns the value of the input `name` parsed as `T`, or None if it is not supplied.
+/// An empty value counts as not supplied, unless `T` parses from it (e.g. `String`).
+/// Use `get_boolean_input` for booleans.
+pub fn get_input<T>(name: impl AsRef<str>) -> Result<Option<T>, InputError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let name = name.as_ref();
+    let Some(value) = get_raw_input(name) else {
+        return Ok(None);
+    };
+    match value.parse() {
+        Ok(x) => Ok(Some(x)),
+        Err(_) if value.is_empty() => Ok(None),
+        Err(e) => Err(InputError::Invalid {
+            name: name.to_string(),
+            value,
+            reason: e.to_string(),
+        }),
+    }
+}
+
+pub fn get_input_required<T>(name: impl AsRef<str>) -> Result<T, InputError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let name = name.as_ref();
+    match get_raw_input(name) {
+        Some(x) if !x.is_empty() => {
+            get_input(name)?.ok_or_else(|| InputError::Required(name.to_string()))
+        }
+        _ => Err(InputError::Required(name.to_string())),
+    }
+}
+
+/// Returns the value of the input `name` as a boolean following the YAML 1.2
+/// "core schema", like `@actions/core`, or None if it is not supplied.
+pub fn get_boolean_input(name: impl AsRef<str>) -> Result<Option<bool>, InputError> {
+    let name = name.as_ref();
+    match get_raw_input(name).as_deref() {
+        None | Some("") => Ok(None),
+        Some("true" | "True" | "TRUE") => Ok(Some(true)),
+        Some("false" | "False" | "FALSE") => Ok(Some(false)),
+        Some(_) => Err(InputError::InvalidBoolean(name.to_string())),
+    }
+}
+
+/// Returns the non-empty lines of the input `name`, trimmed.
+pub fn get_multiline_input(name: impl AsRef<str>) -> Vec<String> {
+    get_raw_input(name.as_ref())
+        .map(|v| {
+            v.lines()
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_get_boolean_input() {
+        let cases = vec![
+            ("true", Some(true)),
+            ("True", Some(true)),
+            (" TRUE\n", Some(true)),
+            ("false", Some(false)),
+            ("False", Some(false)),
+            ("FALSE", Some(false)),
+            ("", None),
+        ];
+        for (value, expected) in cases {
+            env::set_var("INPUT_TEST-BOOLEAN", value);
+            assert_eq!(
+                get_boolean_input("test-boolean").unwrap(),
+                expected,
+                "value: {:?}",
+                value
+            );
+        }
+
+        for value in ["yes", "1", "tRue", "on"] {
+            env::set_var("INPUT_TEST-INVALID-BOOLEAN", value);
+            assert!(
+                matches!(
+                    get_boolean_input("test-invalid-boolean"),
+                    Err(InputError::InvalidBoolean(_))
+                ),
+                "value: {:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_input() {
+        env::set_var("INPUT_TEST_INPUT_NAME", "  value \n");
+        assert_eq!(
+            get_input::<String>("test input-name").unwrap().as_deref(),
+            Some("value")
+        );
+
+        env::set_var("INPUT_TEST-NUMBER", "12x");
+        assert!(matches!(
+            get_input::<u32>("test-number"),
+            Err(InputError::Invalid { .. })
+        ));
+
+        env::set_var("INPUT_TEST-EMPTY", " ");
+        assert_eq!(
+            get_input::<String>("test-empty").unwrap().as_deref(),
+            Some("")
+        );
+        assert_eq!(get_input::<u32>("test-empty").unwrap(), None);
+
+        for name in ["test-empty", "test-missing"] {
+            assert!(matches!(
+                get_input_required::<String>(name),
+                Err(InputError::Required(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_get_multiline_input() {
+        env::set_var("INPUT_TEST-LABELS", "a\n  b \n\nc\n");
+        assert_eq!(get_multiline_input("test-labels"), vec!["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fmt, io};
+
+use crate::file_command::issue_file_command;
+
+/// Builds the Markdown of a job summary.
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    buffer: String,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a heading of the given level, between 1 and 6.
+    pub fn heading(self, text: impl fmt::Display, level: usize) -> Self {
+        let level = level.clamp(1, 6);
+        self.line(format!("{} {}", "#".repeat(level), text))
+    }
+
+    /// Adds a paragraph.
+    pub fn paragraph(self, text: impl fmt::Display) -> Self {
+        self.line(text).line("")
+    }
+
+    /// Adds a bulleted list.
+    pub fn list<I, T>(mut self, items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: fmt::Display,
+    {
+        for item in items {
+            self = self.line(format!("- {}", item));
+        }
+        self.line("")
+    }
+
+    /// Adds a table. Pipes and line breaks in the cells are escaped.
+    pub fn table<H, R, C>(mut self, header: H, rows: R) -> Self
+    where
+        H: IntoIterator,
+        H::Item: fmt::Display,
+        R: IntoIterator,
+        R::Item: IntoIterator<Item = C>,
+        C: fmt::Display,
+    {
+        let header = header.into_iter().map(cell).collect::<Vec<_>>();
+        let separator = vec!["---"; header.len()].join(" | ");
+        self = self
+            .line(format!("| {} |", header.join(" | ")))
+            .line(format!("| {} |", separator));
+        for row in rows {
+            let row = row.into_iter().map(cell).collect::<Vec<_>>();
+            self = self.line(format!("| {} |", row.join(" | ")));
+        }
+        self.line("")
+    }
+
+    /// Adds a Markdown link.
+    pub fn link(self, text: impl fmt::Display, href: impl fmt::Display) -> Self {
+        self.line(format!("[{}]({})", text, href))
+    }
+
+    /// Adds raw Markdown followed by a line break.
+    pub fn line(mut self, text: impl fmt::Display) -> Self {
+        self.buffer.push_str(&text.to_string());
+        self.buffer.push('\n');
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Appends the summary to `$GITHUB_STEP_SUMMARY`.
+    pub fn write(&self) -> io::Result<()> {
+        add_summary(self)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.buffer)
+    }
+}
+
+/// Appends Markdown to the summary of the current job, shown on the run page.
+pub fn add_summary(markdown: impl fmt::Display) -> io::Result<()> {
+    issue_file_command("STEP_SUMMARY", markdown)
+}
+
+fn cell(text: impl fmt::Display) -> String {
+    text.to_string()
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Summary;
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::new()
+            .heading("Cherry-pick results", 3)
+            .table(
+                ["Branch", "Result"],
+                [
+                    vec!["release-1.2".to_string(), "#12".to_string()],
+                    vec!["release|1.1".to_string(), "conflict\nin a.rs".to_string()],
+                ],
+            )
+            .list(["a", "b"]);
+
+        assert_eq!(
+            summary.to_string(),
+            "### Cherry-pick results\n\
+             | Branch | Result |\n\
+             | --- | --- |\n\
+             | release-1.2 | #12 |\n\
+             | release\\|1.1 | conflict<br>in a.rs |\n\
+             \n\
+             - a\n\
+             - b\n\
+             \n"
+        );
+    }
+}
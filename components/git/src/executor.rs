@@ -1,4 +1,10 @@
-use std::{ffi::OsStr, future::Future, pin::Pin, process::Output};
+use std::{
+    ffi::{OsStr, OsString},
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    process::{Output, Stdio},
+};
 
 use futures::future::BoxFuture;
 use tokio::process::Command;
@@ -7,30 +13,61 @@ use crate::{DynExecutor, Executor, ExecutorError};
 
 pub struct GitCommandExecutor {
     git: String,
+    dir: Option<PathBuf>,
+    envs: Vec<(OsString, OsString)>,
 }
 
 impl GitCommandExecutor {
     pub fn new() -> Self {
         Self {
             git: "git".to_string(),
+            dir: None,
+            envs: Vec::new(),
         }
     }
+
+    /// Runs the commands in `dir` instead of the current directory.
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the environment variable `key` for the commands, e.g. for credentials that
+    /// differ between repositories.
+    pub fn env(mut self, key: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
 }
 
 impl DynExecutor for GitCommandExecutor {
     fn exec(&self, args: &[&OsStr]) -> BoxFuture<'static, Result<Output, ExecutorError>> {
-        let cmd_result = Command::new(&self.git)
+        let mut cmd = Command::new(&self.git);
+        if let Some(dir) = &self.dir {
+            cmd.current_dir(dir);
+        }
+        let cmd_result = cmd
             .args(args)
+            // Messages in English whatever the locale of the runner, and a failure instead
+            // of a prompt hanging forever when credentials are missing.
+            .env("LC_ALL", "C")
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .spawn()
-            .map_err(ExecutorError::SpawnError);
+            .map_err(|err| ExecutorError::spawn(args, err));
+        let args: Vec<OsString> = args.iter().map(|&arg| arg.to_owned()).collect();
         Box::pin(async move {
+            let args: Vec<&OsStr> = args.iter().map(OsString::as_os_str).collect();
             cmd_result?
                 .wait_with_output()
                 .await
-                .map_err(ExecutorError::SpawnError)
+                .map_err(|err| ExecutorError::spawn(&args, err))
                 .and_then(|output| {
                     if !output.status.success() {
-                        Err(ExecutorError::OutputError(output))
+                        Err(ExecutorError::output(&args, output))
                     } else {
                         Ok(output)
                     }
@@ -1,19 +1,26 @@
 use std::{
     env,
-    ffi::OsStr,
+    ffi::{OsStr, OsString},
     future::{ready, Future, Ready},
     io,
     path::{Path, PathBuf},
     process::Output,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use executor::GitCommandExecutor;
 use futures::future::BoxFuture;
 use logit::Logit;
 use tokio::fs;
 
 pub mod executor;
+mod mirror;
+mod output;
+mod workspace;
+
+pub use mirror::Mirror;
+pub use output::{ChangeKind, Commit, EntryKind, FileChange, ObjectId, Status, StatusEntry};
+pub use workspace::Workspace;
 
 pub struct GitBuilder {
     owner: String,
@@ -22,6 +29,7 @@ pub struct GitBuilder {
     executor: Option<Box<dyn DynExecutor + Send>>,
     dir: Option<PathBuf>,
     git_user_getter: Option<Box<dyn DynGitUserGetter + Send>>,
+    envs: Vec<(OsString, OsString)>,
 }
 
 impl GitBuilder {
@@ -33,6 +41,7 @@ impl GitBuilder {
             dir: None,
             executor: None,
             git_user_getter: None,
+            envs: Vec::new(),
         }
     }
 
@@ -56,11 +65,15 @@ impl GitBuilder {
         self
     }
 
+    /// Sets the environment variable `key` for the git commands, unless a custom executor
+    /// is used.
+    pub fn env(mut self, key: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
     pub fn build(self) -> Git {
         let censor = self.censor.unwrap_or(|x: &OsStr| x);
-        let executor = self
-            .executor
-            .unwrap_or_else(|| Box::new(GitCommandExecutor::new()));
         let git_user_getter = self
             .git_user_getter
             .unwrap_or_else(|| Box::new(DefaultGitUserGetter {}));
@@ -70,23 +83,106 @@ impl GitBuilder {
                 .join(self.owner)
                 .join(self.repo)
         });
+        let envs = self.envs;
+        let executor = self.executor.unwrap_or_else(|| {
+            Box::new(envs.into_iter().fold(
+                GitCommandExecutor::new().current_dir(&dir),
+                |executor, (k, v)| executor.env(k, v),
+            ))
+        });
 
         let _ = std::fs::create_dir_all(&dir).logit("create git dir");
         Git {
             dir,
             info: git_user_getter,
             executor: Box::new(CensoringExecutor::new(censor, executor)),
+            depth: None,
         }
     }
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum ExecutorError {
-    #[error("spawn command error {0:?}")]
-    SpawnError(io::Error),
+    #[error("failed to run `{command}`: {source}")]
+    SpawnError {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
     /// Spawn a command succeed, but the command exit status is error.
-    #[error("Spawn a command succeed, but the command exit status is error {0:?}")]
-    OutputError(Output),
+    #[error("`{command}` {}{}", exit_description(.code), stderr_description(.stderr))]
+    OutputError {
+        command: String,
+        /// None if the command was killed by a signal.
+        code: Option<i32>,
+        /// The standard error of the command, trimmed.
+        stderr: String,
+        output: Output,
+    },
+}
+
+impl ExecutorError {
+    /// Failure to spawn `git args`.
+    pub fn spawn(args: &[&OsStr], source: io::Error) -> Self {
+        Self::SpawnError {
+            command: command_line(args),
+            source,
+        }
+    }
+
+    /// Failure of `git args`, which exited with `output`.
+    pub fn output(args: &[&OsStr], output: Output) -> Self {
+        Self::OutputError {
+            command: command_line(args),
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            output,
+        }
+    }
+
+    // Replaces the command line with its censored version, and the censored arguments git
+    // repeated in its messages, e.g. a url in "unable to access".
+    fn censor(mut self, censored_args: &[&OsStr], args: &[&OsStr]) -> Self {
+        match &mut self {
+            Self::SpawnError { command, .. } => *command = command_line(censored_args),
+            Self::OutputError {
+                command, stderr, ..
+            } => {
+                *command = command_line(censored_args);
+                for (arg, censored) in args.iter().zip(censored_args) {
+                    if arg != censored && !arg.is_empty() {
+                        *stderr =
+                            stderr.replace(&*arg.to_string_lossy(), &censored.to_string_lossy());
+                    }
+                }
+            }
+        }
+        self
+    }
+}
+
+fn command_line(args: &[&OsStr]) -> String {
+    let mut line = "git".to_string();
+    for arg in args {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    line
+}
+
+fn exit_description(code: &Option<i32>) -> String {
+    match code {
+        Some(code) => format!("exited with code {}", code),
+        None => "was killed by a signal".to_string(),
+    }
+}
+
+fn stderr_description(stderr: &str) -> String {
+    if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(":\n{}", stderr)
+    }
 }
 
 pub trait Executor {
@@ -130,13 +226,18 @@ impl CensoringExecutor {
 impl Executor for CensoringExecutor {
     type Fut<'a> = BoxFuture<'a, Result<Output, ExecutorError>>;
 
+    // Runs the command as is, censoring only the command line reported on failure.
     fn exec(&self, args: &[&OsStr]) -> Self::Fut<'_> {
-        let mut censored = Vec::with_capacity(args.len());
-        for arg in args {
-            censored.push((self.censor)(arg.as_ref()));
-        }
-
-        self.inner.exec(&censored)
+        let fut = self.inner.exec(args);
+        let censor = self.censor;
+        let args: Vec<OsString> = args.iter().map(|&arg| arg.to_owned()).collect();
+        Box::pin(async move {
+            fut.await.map_err(|error| {
+                let args: Vec<&OsStr> = args.iter().map(OsString::as_os_str).collect();
+                let censored: Vec<&OsStr> = args.iter().map(|arg| censor(arg)).collect();
+                error.censor(&censored, &args)
+            })
+        })
     }
 }
 
@@ -179,10 +280,74 @@ impl GitUserGetter for DefaultGitUserGetter {
     }
 }
 
+/// Options of `Git::clone`, which clones the full history of every branch by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CloneOptions {
+    /// Clones only the last `depth` commits, deepened by `am` when needed.
+    pub depth: Option<u32>,
+    /// Omits the objects matching the filter, e.g. `blob:none`, fetching them on demand.
+    pub filter: Option<String>,
+    /// Clones only this branch and checks it out.
+    pub branch: Option<String>,
+    pub no_tags: bool,
+}
+
+impl CloneOptions {
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(depth) = self.depth {
+            args.push(format!("--depth={}", depth));
+        }
+        if let Some(filter) = &self.filter {
+            args.push(format!("--filter={}", filter));
+        }
+        if let Some(branch) = &self.branch {
+            args.push("--single-branch".to_string());
+            args.push(format!("--branch={}", branch));
+        }
+        if self.no_tags {
+            args.push("--no-tags".to_string());
+        }
+        args
+    }
+}
+
+/// Options of `Git::cherry_pick`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CherryPickOptions {
+    /// Picks merge commits relative to this parent, starting from 1.
+    pub mainline: Option<u32>,
+    /// Appends `(cherry picked from commit ...)` to the messages.
+    pub record_origin: bool,
+    /// Keeps the commits that become empty instead of failing.
+    pub allow_empty: bool,
+}
+
+impl CherryPickOptions {
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.record_origin {
+            args.push("-x".to_string());
+        }
+        if let Some(mainline) = self.mainline {
+            args.push(format!("--mainline={}", mainline));
+        }
+        if self.allow_empty {
+            args.push("--allow-empty".to_string());
+        }
+        args
+    }
+}
+
+// Times `am` deepens a shallow clone, doubling the depth each time.
+const MAX_DEEPEN: u32 = 4;
+
 pub struct Git {
     dir: PathBuf,
     info: Box<dyn DynGitUserGetter + Send>,
     executor: Box<dyn DynExecutor + Send>,
+    // The depth of a shallow clone.
+    depth: Option<u32>,
 }
 
 impl Git {
@@ -195,27 +360,93 @@ impl Git {
             dir: dir.into(),
             info,
             executor,
+            depth: None,
         }
     }
 
+    /// Returns the depth of a shallow clone, before it was deepened.
+    pub fn depth(&self) -> Option<u32> {
+        self.depth
+    }
+
     /// Exposes the directory in which the repository has been cloned
     pub fn directory(&self) -> &Path {
         &self.dir
     }
 
     /// Clone clones the repository from a local path.
-    pub async fn clone(&mut self, from: impl AsRef<OsStr>) -> Result<()> {
+    pub async fn clone(&mut self, from: impl AsRef<OsStr>, options: &CloneOptions) -> Result<()> {
+        tracing::info!(
+            "Creating a clone of the repo at {} from {:?} with {:?}",
+            self.dir.display(),
+            from.as_ref(),
+            options
+        );
+
+        let options_args = options.args();
+        let mut args = vec![OsStr::new("clone")];
+        args.extend(options_args.iter().map(OsStr::new));
+        args.extend([from.as_ref(), self.dir.as_os_str()]);
+        self.executor
+            .exec(&args)
+            .await
+            .context("error creating a clone")?;
+        self.depth = options.depth;
+        Ok(())
+    }
+
+    /// Creates a bare clone of the repository at `from`, without a working tree.
+    pub async fn clone_bare(&mut self, from: impl AsRef<OsStr>) -> Result<()> {
         tracing::info!(
-            "Creating a clone of the repo at {} from {:?}",
+            "Creating a bare clone of the repo at {} from {:?}",
             self.dir.display(),
             from.as_ref()
         );
 
         self.executor
-            .exec(&[OsStr::new("clone"), from.as_ref(), self.dir.as_os_str()])
+            .exec(&[
+                OsStr::new("clone"),
+                OsStr::new("--bare"),
+                from.as_ref(),
+                self.dir.as_os_str(),
+            ])
+            .await
+            .map(|_| ())
+            .context("error creating a bare clone")
+    }
+
+    /// Checks out `commitlike` with a detached HEAD in a new worktree at `path`, which
+    /// must not exist or be empty.
+    pub async fn worktree_add(
+        &mut self,
+        path: impl AsRef<Path>,
+        commitlike: impl AsRef<OsStr>,
+    ) -> Result<()> {
+        tracing::info!(
+            "Adding a worktree of {:?} at {}",
+            commitlike.as_ref(),
+            path.as_ref().display()
+        );
+        self.executor
+            .exec(&[
+                OsStr::new("worktree"),
+                OsStr::new("add"),
+                OsStr::new("--detach"),
+                path.as_ref().as_os_str(),
+                commitlike.as_ref(),
+            ])
+            .await
+            .with_context(|| format!("error adding worktree {}", path.as_ref().display()))
+            .map(|_| ())
+    }
+
+    /// Forgets the worktrees whose directory was removed.
+    pub async fn worktree_prune(&mut self) -> Result<()> {
+        self.executor
+            .exec(&[OsStr::new("worktree"), OsStr::new("prune")])
             .await
+            .context("error pruning worktrees")
             .map(|_| ())
-            .context("error creating a clone")
     }
 
     /// Stages all changes and commits them with the message
@@ -278,20 +509,262 @@ impl Git {
     // Tries to apply the patch in the given path into the current branch
     // by performing a three-way merge (similar to git cherry-pick). It returns
     // an error if the patch cannot be applied.
+    // A shallow clone is deepened while the blobs the patch applies to, which the
+    // three-way merge needs, are missing.
     pub async fn am(&mut self, path: impl AsRef<OsStr>) -> Result<()> {
-        tracing::info!("Applying patch at {:?}", path.as_ref());
-        if let Err(error) = self
+        let mut deepened = 0;
+        loop {
+            tracing::info!("Applying patch at {:?}", path.as_ref());
+            let error = match self
+                .executor
+                .exec(&[OsStr::new("am"), OsStr::new("--3way"), path.as_ref()])
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(error) => error,
+            };
+            tracing::info!(error=%error, "Patch apply failed");
+            if let ExecutorError::OutputError { .. } = &error {
+                let _ = self
+                    .executor
+                    .exec(&[OsStr::new("am"), OsStr::new("--abort")])
+                    .await
+                    .logit_warn("Aborting patch apply failed");
+            }
+
+            let depth = match self.depth {
+                Some(depth) if deepened < MAX_DEEPEN => depth,
+                _ => return Err(error.into()),
+            };
+            let missing = self.missing_base_blobs(Path::new(path.as_ref())).await?;
+            if missing.is_empty() {
+                return Err(error.into());
+            }
+            tracing::info!(
+                missing = ?missing,
+                "Deepening the clone by {} commits for the base blobs of the patch",
+                depth
+            );
+            self.deepen("origin", depth).await?;
+            self.depth = Some(depth * 2);
+            deepened += 1;
+        }
+    }
+
+    /// Returns the blobs the patch at `path` applies to that are missing from the repository.
+    async fn missing_base_blobs(&mut self, path: &Path) -> Result<Vec<String>> {
+        let patch = fs::read(path)
+            .await
+            .with_context(|| format!("error reading patch {}", path.display()))?;
+        let mut missing = Vec::new();
+        for blob in base_blobs(&String::from_utf8_lossy(&patch)) {
+            if self
+                .executor
+                .exec(&[OsStr::new("cat-file"), OsStr::new("-e"), OsStr::new(blob)])
+                .await
+                .is_err()
+            {
+                missing.push(blob.to_string());
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Fetches `depth` more commits of the history of a shallow clone from `remote`.
+    pub async fn deepen(&mut self, remote: impl AsRef<OsStr>, depth: u32) -> Result<()> {
+        tracing::info!("Deepening {:?} by {}", remote.as_ref(), depth);
+        let deepen = format!("--deepen={}", depth);
+        self.executor
+            .exec(&[OsStr::new("fetch"), OsStr::new(&deepen), remote.as_ref()])
+            .await
+            .with_context(|| format!("error deepening {:?}", remote.as_ref()))
+            .map(|_| ())
+    }
+
+    /// Fetches `refspecs` from `remote`, which is either a remote name or a url, with only
+    /// the last `depth` commits of their history if given.
+    pub async fn fetch(
+        &mut self,
+        remote: impl AsRef<OsStr>,
+        refspecs: &[impl AsRef<OsStr>],
+        depth: Option<u32>,
+    ) -> Result<()> {
+        let depth = depth.map(|depth| format!("--depth={}", depth));
+        let mut args = vec![OsStr::new("fetch")];
+        args.extend(depth.iter().map(OsStr::new));
+        args.push(remote.as_ref());
+        let refspecs_start = args.len();
+        args.extend(refspecs.iter().map(AsRef::as_ref));
+        tracing::info!(
+            "Fetching {:?} from {:?}",
+            &args[refspecs_start..],
+            remote.as_ref()
+        );
+        self.executor
+            .exec(&args)
+            .await
+            .with_context(|| format!("error fetching {:?}", &args[refspecs_start..]))
+            .map(|_| ())
+    }
+
+    /// Adds the remote `name` fetching from `url`.
+    pub async fn add_remote(
+        &mut self,
+        name: impl AsRef<OsStr>,
+        url: impl AsRef<OsStr>,
+    ) -> Result<()> {
+        tracing::info!("Adding remote {:?}", name.as_ref());
+        self.executor
+            .exec(&[
+                OsStr::new("remote"),
+                OsStr::new("add"),
+                name.as_ref(),
+                url.as_ref(),
+            ])
+            .await
+            .with_context(|| format!("error adding remote {:?}", name.as_ref()))
+            .map(|_| ())
+    }
+
+    /// Returns the object `rev` names, e.g. `HEAD` or `origin/main~2`.
+    pub async fn rev_parse(&mut self, rev: impl AsRef<OsStr>) -> Result<ObjectId> {
+        self.output(&[
+            OsStr::new("rev-parse"),
+            OsStr::new("--verify"),
+            rev.as_ref(),
+        ])
+        .await
+        .with_context(|| format!("error resolving {:?}", rev.as_ref()))?
+        .trim()
+        .parse()
+    }
+
+    /// Returns the best common ancestor of `a` and `b`, or None if they have none.
+    pub async fn merge_base(
+        &mut self,
+        a: impl AsRef<OsStr>,
+        b: impl AsRef<OsStr>,
+    ) -> Result<Option<ObjectId>> {
+        match self
             .executor
-            .exec(&[OsStr::new("am"), OsStr::new("--3way"), path.as_ref()])
+            .exec(&[OsStr::new("merge-base"), a.as_ref(), b.as_ref()])
             .await
         {
-            tracing::info!(error=?error, "Patch apply failed");
-            if let ExecutorError::OutputError(_) = &error {
+            Ok(output) => Ok(Some(
+                String::from_utf8_lossy(&output.stdout).trim().parse()?,
+            )),
+            // Exits with 1 without printing anything if there is no common ancestor.
+            Err(ExecutorError::OutputError {
+                code: Some(1),
+                output,
+                ..
+            }) if output.stdout.is_empty() => Ok(None),
+            Err(error) => Err(error).with_context(|| {
+                format!("error finding merge base of {:?}", (a.as_ref(), b.as_ref()))
+            }),
+        }
+    }
+
+    /// Returns the commits of `range`, e.g. `main..feature`, newest first.
+    pub async fn log(&mut self, range: impl AsRef<OsStr>) -> Result<Vec<Commit>> {
+        let output = self
+            .output(&[
+                OsStr::new("log"),
+                OsStr::new(output::LOG_FORMAT),
+                range.as_ref(),
+                OsStr::new("--"),
+            ])
+            .await
+            .with_context(|| format!("error listing commits of {:?}", range.as_ref()))?;
+        output::parse_log(&output)
+    }
+
+    /// Returns the files changed by `range`, e.g. `main...feature`, detecting renames.
+    pub async fn diff_name_status(&mut self, range: impl AsRef<OsStr>) -> Result<Vec<FileChange>> {
+        let output = self
+            .output(&[
+                OsStr::new("diff"),
+                OsStr::new("--name-status"),
+                OsStr::new("--find-renames"),
+                OsStr::new("-z"),
+                range.as_ref(),
+                OsStr::new("--"),
+            ])
+            .await
+            .with_context(|| format!("error diffing {:?}", range.as_ref()))?;
+        output::parse_name_status(&output)
+    }
+
+    /// Returns the state of the branch and the working tree.
+    pub async fn status(&mut self) -> Result<Status> {
+        let output = self
+            .output(&[
+                OsStr::new("status"),
+                OsStr::new("--porcelain=v2"),
+                OsStr::new("--branch"),
+                OsStr::new("-z"),
+            ])
+            .await
+            .context("error getting status")?;
+        output::parse_status(&output)
+    }
+
+    /// Runs a command, returning its output.
+    async fn output(&mut self, args: &[&OsStr]) -> Result<String> {
+        let output = self.executor.exec(args).await?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Writes the commits of `range` to `path` as an mbox with binary diffs, which `am`
+    /// applies. Unlike the patches rendered by GitHub, it is not truncated for large changes.
+    pub async fn format_patch(
+        &mut self,
+        range: impl AsRef<OsStr>,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        tracing::info!(
+            "Formatting patch of {:?} to {}",
+            range.as_ref(),
+            path.as_ref().display()
+        );
+        let output = self
+            .executor
+            .exec(&[
+                OsStr::new("format-patch"),
+                OsStr::new("--stdout"),
+                OsStr::new("--binary"),
+                range.as_ref(),
+            ])
+            .await
+            .with_context(|| format!("error formatting patch of {:?}", range.as_ref()))?;
+        if output.stdout.is_empty() {
+            bail!("no commits in {:?}", range.as_ref());
+        }
+        fs::write(path.as_ref(), output.stdout)
+            .await
+            .with_context(|| format!("error writing patch {}", path.as_ref().display()))
+    }
+
+    // Cherry-picks the given commits onto the current branch, in order.
+    // It returns an error if one of them cannot be applied, leaving the branch as it was.
+    pub async fn cherry_pick(
+        &mut self,
+        commits: &[impl AsRef<OsStr>],
+        options: &CherryPickOptions,
+    ) -> Result<()> {
+        let options_args = options.args();
+        let mut args = vec![OsStr::new("cherry-pick")];
+        args.extend(options_args.iter().map(OsStr::new));
+        args.extend(commits.iter().map(AsRef::as_ref));
+        tracing::info!("Cherry-picking {:?}", &args[1..]);
+        if let Err(error) = self.executor.exec(&args).await {
+            tracing::info!(error=%error, "Cherry-pick failed");
+            if let ExecutorError::OutputError { .. } = &error {
                 let _ = self
                     .executor
-                    .exec(&[OsStr::new("am"), OsStr::new("--abort")])
+                    .exec(&[OsStr::new("cherry-pick"), OsStr::new("--abort")])
                     .await
-                    .logit_warn("Aborting patch apply failed");
+                    .logit_warn("Aborting cherry-pick failed");
             }
             return Err(error.into());
         }
@@ -320,6 +793,23 @@ impl Git {
             .map(|_| ())
     }
 
+    /// Creates or resets the branch to the current commit and checks it out,
+    /// discarding local changes.
+    pub async fn checkout_reset_branch(&mut self, branch: impl AsRef<OsStr>) -> Result<()> {
+        tracing::info!("Checking out reset branch {:?}", branch.as_ref());
+
+        self.executor
+            .exec(&[
+                OsStr::new("checkout"),
+                OsStr::new("--force"),
+                OsStr::new("-B"),
+                branch.as_ref(),
+            ])
+            .await
+            .with_context(|| format!("error checking out reset branch {:?}", branch.as_ref()))
+            .map(|_| ())
+    }
+
     /// Returns true if branch exists in heads.
     pub async fn branch_exists(&mut self, branch: impl AsRef<OsStr>) -> bool {
         tracing::info!("Checking if branch {:?} exists", branch.as_ref());
@@ -350,3 +840,155 @@ impl Git {
             .map(|_| ())
     }
 }
+
+/// Returns the blobs the changes of `patch` apply to, from the `index <from>..<to>` lines
+/// of its diffs, except for the files it creates.
+fn base_blobs(patch: &str) -> impl Iterator<Item = &str> {
+    patch
+        .lines()
+        .filter_map(|line| line.strip_prefix("index "))
+        .filter_map(|range| range.split("..").next())
+        .filter(|blob| !blob.is_empty() && blob.bytes().any(|b| b != b'0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_blobs() {
+        let cases = vec![
+            // Modified file, with the mode on the index line.
+            (
+                "diff --git a/a.rs b/a.rs\nindex 1a2b3c4..5d6e7f8 100644\n--- a/a.rs\n+++ b/a.rs\n",
+                vec!["1a2b3c4"],
+            ),
+            // New file.
+            (
+                "diff --git a/b.rs b/b.rs\nnew file mode 100644\nindex 0000000..5d6e7f8\n",
+                vec![],
+            ),
+            // Deleted file, which still needs its blob.
+            (
+                "diff --git a/c.rs b/c.rs\ndeleted file mode 100644\nindex 1a2b3c4..0000000\n",
+                vec!["1a2b3c4"],
+            ),
+            // Mode change, without an index line for the content.
+            (
+                "diff --git a/d.sh b/d.sh\nold mode 100644\nnew mode 100755\n",
+                vec![],
+            ),
+            // Mode and content change, with full object names.
+            (
+                "diff --git a/e.sh b/e.sh\nold mode 100644\nnew mode 100755\n\
+                 index 83db48f84ec878fbfb30b46d16630e944e34f205..bf269f4f8b8b7d4a1e0d5c1a0c5b6c1d2e3f4a5b\n",
+                vec!["83db48f84ec878fbfb30b46d16630e944e34f205"],
+            ),
+            // Lines of the diff itself are prefixed, so never taken for index lines.
+            (
+                "index 1a2b3c4..5d6e7f8 100644\n@@ -1 +1 @@\n-index 7777777..8888888\n+index 9999999..aaaaaaa\n",
+                vec!["1a2b3c4"],
+            ),
+        ];
+        for (patch, expected) in cases {
+            assert_eq!(
+                base_blobs(patch).collect::<Vec<_>>(),
+                expected,
+                "patch: {:?}",
+                patch
+            );
+        }
+    }
+
+    #[test]
+    fn test_clone_options_args() {
+        let cases = vec![
+            (CloneOptions::default(), vec![]),
+            (
+                CloneOptions {
+                    depth: Some(50),
+                    ..Default::default()
+                },
+                vec!["--depth=50"],
+            ),
+            (
+                CloneOptions {
+                    filter: Some("blob:none".to_string()),
+                    ..Default::default()
+                },
+                vec!["--filter=blob:none"],
+            ),
+            (
+                CloneOptions {
+                    branch: Some("release-1.0".to_string()),
+                    ..Default::default()
+                },
+                vec!["--single-branch", "--branch=release-1.0"],
+            ),
+            (
+                CloneOptions {
+                    no_tags: true,
+                    ..Default::default()
+                },
+                vec!["--no-tags"],
+            ),
+            (
+                CloneOptions {
+                    depth: Some(1),
+                    filter: Some("tree:0".to_string()),
+                    branch: Some("main".to_string()),
+                    no_tags: true,
+                },
+                vec![
+                    "--depth=1",
+                    "--filter=tree:0",
+                    "--single-branch",
+                    "--branch=main",
+                    "--no-tags",
+                ],
+            ),
+        ];
+        for (options, expected) in cases {
+            assert_eq!(options.args(), expected, "{:?}", options);
+        }
+    }
+
+    fn censor(arg: &OsStr) -> &OsStr {
+        if arg.to_string_lossy().contains("s3cret") {
+            OsStr::new("<censored>")
+        } else {
+            arg
+        }
+    }
+
+    #[tokio::test]
+    async fn test_censoring_executor() {
+        let dir = tempfile::tempdir().unwrap();
+        let executor = CensoringExecutor::new(
+            censor,
+            Box::new(GitCommandExecutor::new().current_dir(dir.path())),
+        );
+        let args = [
+            OsStr::new("rev-parse"),
+            OsStr::new("--verify"),
+            OsStr::new("s3cret"),
+        ];
+
+        // The command runs with the secret, but only its censored version is reported.
+        let error = Executor::exec(&executor, &args).await.unwrap_err();
+        match &error {
+            ExecutorError::OutputError {
+                command,
+                code,
+                stderr,
+                ..
+            } => {
+                assert_eq!(command, "git rev-parse --verify <censored>");
+                assert_eq!(*code, Some(128));
+                assert!(stderr.starts_with("fatal: "), "{:?}", stderr);
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert!(!error.to_string().contains("s3cret"), "{}", error);
+    }
+}
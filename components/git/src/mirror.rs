@@ -0,0 +1,229 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use logit::Logit;
+use tokio::time;
+
+use crate::{GitBuilder, Workspace};
+
+// Waiting for another pick to update the mirror longer than this fails instead.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+// A lock older than this was left behind by a pick that was killed.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A bare clone of a repository kept in a cache directory across picks, e.g. one restored
+/// with `actions/cache` or on a persistent runner. Workspaces are checked out from it as
+/// worktrees, fetching only the branch they need instead of cloning the whole repository.
+pub struct Mirror {
+    dir: PathBuf,
+}
+
+impl Mirror {
+    /// Uses the bare clone at `dir`, which is created on first use. A relative `dir`, e.g.
+    /// the path of an `actions/cache` step, is relative to the current directory rather than
+    /// to the directories git runs in.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let dir = match env::current_dir() {
+            Ok(current_dir) if dir.is_relative() => current_dir.join(dir),
+            _ => dir,
+        };
+        Self { dir }
+    }
+
+    /// Updates `branch` from `url`, cloning the mirror first if needed, and checks it out in
+    /// a new workspace built with `builder`. The mirror is locked meanwhile, so that
+    /// concurrent picks, in this process or not, update it one at a time.
+    pub async fn workspace(
+        &self,
+        builder: GitBuilder,
+        url: impl AsRef<OsStr>,
+        branch: impl AsRef<str>,
+    ) -> Result<Workspace> {
+        let branch = branch.as_ref();
+        let _lock = Lock::acquire(self.dir.with_extension("lock")).await?;
+
+        let mut mirror = GitBuilder::new(&builder.owner, &builder.repo).dir(self.dir.clone());
+        for (key, value) in &builder.envs {
+            mirror = mirror.env(key, value);
+        }
+        let mut mirror = mirror.build();
+        if self.dir.join("HEAD").exists() {
+            // Worktrees of the workspaces removed since the last pick.
+            let _ = mirror.worktree_prune().await.logit_warn("prune worktrees");
+            mirror
+                .fetch(
+                    url,
+                    &[format!("+refs/heads/{0}:refs/heads/{0}", branch)],
+                    None,
+                )
+                .await
+                .context("update mirror")?;
+        } else {
+            mirror.clone_bare(url).await.context("create mirror")?;
+        }
+
+        let workspace = Workspace::new(builder).context("create workspace")?;
+        mirror
+            .worktree_add(workspace.directory(), format!("refs/heads/{}", branch))
+            .await
+            .context("check out worktree")?;
+        Ok(workspace)
+    }
+}
+
+/// A lock file next to the mirror, created exclusively like the lock files of git.
+struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    async fn acquire(path: PathBuf) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("create cache directory {}", parent.display()))?;
+        }
+        let started = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    if is_stale(&path) {
+                        tracing::warn!("Removing stale lock {}", path.display());
+                        let _ = fs::remove_file(&path);
+                        continue;
+                    }
+                    if started.elapsed() > LOCK_TIMEOUT {
+                        bail!("timed out waiting for the lock {}", path.display());
+                    }
+                    time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| format!("create lock {}", path.display()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path).logit_warn("remove lock");
+    }
+}
+
+fn is_stale(path: &Path) -> bool {
+    let age = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    matches!(age, Some(age) if age > STALE_LOCK_AGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    // Commits `name` to the main branch of the bare repository `upstream`.
+    fn commit(upstream: &Path, name: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        git(
+            dir.path(),
+            &["clone", "--quiet", upstream.to_str().unwrap(), "."],
+        );
+        fs::write(dir.path().join(name), name).unwrap();
+        git(dir.path(), &["add", name]);
+        git(dir.path(), &["commit", "--quiet", "-m", name]);
+        git(dir.path(), &["push", "--quiet", "origin", "HEAD:main"]);
+    }
+
+    #[test]
+    fn test_new() {
+        let mirror = Mirror::new("cache/owner/repo.git");
+        assert!(mirror.dir.is_absolute());
+        assert!(mirror.dir.ends_with("cache/owner/repo.git"));
+    }
+
+    #[tokio::test]
+    async fn test_workspace() {
+        let root = tempfile::tempdir().unwrap();
+        let upstream = root.path().join("upstream.git");
+        git(
+            root.path(),
+            &[
+                "init",
+                "--quiet",
+                "--bare",
+                "--initial-branch=main",
+                "upstream.git",
+            ],
+        );
+        commit(&upstream, "a");
+
+        let mirror = Mirror::new(root.path().join("cache/owner/repo.git"));
+        let workspace = mirror
+            .workspace(GitBuilder::new("owner", "repo"), &upstream, "main")
+            .await
+            .unwrap();
+        assert!(workspace.directory().join("a").exists());
+        assert!(mirror.dir.join("HEAD").exists());
+        workspace.close().await.unwrap();
+
+        // The second workspace updates the mirror instead of cloning it again.
+        commit(&upstream, "b");
+        let workspace = mirror
+            .workspace(GitBuilder::new("owner", "repo"), &upstream, "main")
+            .await
+            .unwrap();
+        assert!(workspace.directory().join("b").exists());
+        assert!(!mirror.dir.with_extension("lock").exists());
+        workspace.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo.lock");
+
+        let lock = Lock::acquire(path.clone()).await.unwrap();
+        let waiting = tokio::spawn(Lock::acquire(path.clone()));
+        time::sleep(Duration::from_millis(1500)).await;
+        assert!(!waiting.is_finished());
+        drop(lock);
+        let lock = waiting.await.unwrap().unwrap();
+        drop(lock);
+        assert!(!path.exists());
+
+        // A lock left behind by a killed pick is taken over.
+        fs::write(&path, "").unwrap();
+        Command::new("touch")
+            .args(["-d", "2 hours ago"])
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(is_stale(&path));
+        let _lock = time::timeout(Duration::from_secs(5), Lock::acquire(path.clone()))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
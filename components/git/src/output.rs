@@ -0,0 +1,441 @@
+//! Typed results of git commands, parsed from their machine-readable output.
+
+use std::{ffi::OsStr, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+// Separates the fields of a commit and the commits in the output of `Git::log`.
+pub(crate) const FIELD_SEPARATOR: char = '\x1f';
+pub(crate) const RECORD_SEPARATOR: char = '\x1e';
+pub(crate) const LOG_FORMAT: &str =
+    "--format=%H%x1f%P%x1f%an%x1f%ae%x1f%s%x1f%b%x1f%(trailers:only,unfold)%x1e";
+
+/// The full hexadecimal name of a git object.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectId(String);
+
+impl ObjectId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // SHA-1 or SHA-256.
+        if !matches!(s.len(), 40 | 64) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("invalid object id {:?}", s);
+        }
+        Ok(Self(s.to_ascii_lowercase()))
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<OsStr> for ObjectId {
+    fn as_ref(&self) -> &OsStr {
+        OsStr::new(&self.0)
+    }
+}
+
+/// A commit listed by `Git::log`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub id: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author_name: String,
+    pub author_email: String,
+    pub subject: String,
+    /// The message after the subject, including the trailers.
+    pub body: String,
+    /// The trailers of the message, e.g. `("Signed-off-by", "...")`, in order.
+    pub trailers: Vec<(String, String)>,
+}
+
+impl Commit {
+    /// Returns the values of the trailers named `key`, compared case-insensitively.
+    pub fn trailer<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.trailers
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub(crate) fn parse_log(output: &str) -> Result<Vec<Commit>> {
+    output
+        .split(RECORD_SEPARATOR)
+        .map(|record| record.trim_start_matches('\n'))
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+            let fields = record.split(FIELD_SEPARATOR).collect::<Vec<_>>();
+            let [id, parents, author_name, author_email, subject, body, trailers] = fields[..]
+            else {
+                bail!("unexpected log record {:?}", record);
+            };
+            Ok(Commit {
+                id: id.parse()?,
+                parents: parents
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<Result<_>>()?,
+                author_name: author_name.to_string(),
+                author_email: author_email.to_string(),
+                subject: subject.to_string(),
+                body: body.trim_end().to_string(),
+                trailers: trailers
+                    .lines()
+                    .filter_map(|line| {
+                        let (key, value) = line.split_once(':')?;
+                        Some((key.trim().to_string(), value.trim().to_string()))
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// How a file was changed, as reported by `git diff --name-status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    /// Renamed from `from`, with a similarity `score` in percent.
+    Renamed {
+        from: String,
+        score: u8,
+    },
+    /// Copied from `from`, with a similarity `score` in percent.
+    Copied {
+        from: String,
+        score: u8,
+    },
+    TypeChanged,
+    Unmerged,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub kind: ChangeKind,
+    pub path: String,
+}
+
+/// Parses the output of `git diff --name-status -z`.
+pub(crate) fn parse_name_status(output: &str) -> Result<Vec<FileChange>> {
+    let mut fields = output.split('\0').filter(|x| !x.is_empty());
+    let mut changes = Vec::new();
+    while let Some(status) = fields.next() {
+        let mut path = || {
+            fields
+                .next()
+                .map(str::to_string)
+                .with_context(|| format!("missing path of status {:?}", status))
+        };
+        let score = || -> Result<u8> {
+            status[1..]
+                .parse()
+                .with_context(|| format!("invalid score in status {:?}", status))
+        };
+        let kind = match status.chars().next() {
+            Some('A') => ChangeKind::Added,
+            Some('M') => ChangeKind::Modified,
+            Some('D') => ChangeKind::Deleted,
+            Some('R') => ChangeKind::Renamed {
+                from: path()?,
+                score: score()?,
+            },
+            Some('C') => ChangeKind::Copied {
+                from: path()?,
+                score: score()?,
+            },
+            Some('T') => ChangeKind::TypeChanged,
+            Some('U') => ChangeKind::Unmerged,
+            Some('X') => ChangeKind::Unknown,
+            _ => bail!("unexpected status {:?}", status),
+        };
+        changes.push(FileChange {
+            kind,
+            path: path()?,
+        });
+    }
+    Ok(changes)
+}
+
+/// The kind of an entry of `git status --porcelain=v2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    Changed,
+    /// Renamed or copied from `from`.
+    Renamed {
+        from: String,
+    },
+    /// Has merge conflicts.
+    Unmerged,
+    Untracked,
+    Ignored,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusEntry {
+    pub kind: EntryKind,
+    /// The status in the index and in the working tree, e.g. `'M'`, `'.'` if unchanged.
+    pub index: char,
+    pub worktree: char,
+    pub path: String,
+}
+
+/// The state of the working tree, from `git status --porcelain=v2 --branch`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// The checked out commit, None before the first commit.
+    pub oid: Option<ObjectId>,
+    /// The checked out branch, None if HEAD is detached.
+    pub branch: Option<String>,
+    pub upstream: Option<String>,
+    /// How many commits the branch is ahead and behind of its upstream.
+    pub ahead_behind: Option<(u32, u32)>,
+    pub entries: Vec<StatusEntry>,
+}
+
+impl Status {
+    /// Returns true if there are no changes, untracked files aside.
+    pub fn is_clean(&self) -> bool {
+        self.entries
+            .iter()
+            .all(|entry| matches!(entry.kind, EntryKind::Untracked | EntryKind::Ignored))
+    }
+
+    /// Returns the paths with merge conflicts.
+    pub fn conflicts(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::Unmerged)
+            .map(|entry| entry.path.as_str())
+    }
+}
+
+/// Parses the output of `git status --porcelain=v2 --branch -z`.
+pub(crate) fn parse_status(output: &str) -> Result<Status> {
+    let mut status = Status::default();
+    let mut records = output.split('\0').filter(|x| !x.is_empty());
+    while let Some(record) = records.next() {
+        let invalid = || anyhow!("unexpected status record {:?}", record);
+        if let Some(header) = record.strip_prefix("# ") {
+            let (key, value) = header.split_once(' ').ok_or_else(invalid)?;
+            match key {
+                "branch.oid" if value != "(initial)" => status.oid = Some(value.parse()?),
+                "branch.head" if value != "(detached)" => status.branch = Some(value.to_string()),
+                "branch.upstream" => status.upstream = Some(value.to_string()),
+                "branch.ab" => {
+                    let (ahead, behind) = value.split_once(' ').ok_or_else(invalid)?;
+                    let count = |x: &str, sign| {
+                        x.strip_prefix(sign)
+                            .and_then(|x| x.parse().ok())
+                            .ok_or_else(invalid)
+                    };
+                    status.ahead_behind = Some((count(ahead, "+")?, count(behind, "-")?));
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let (kind, rest) = record.split_once(' ').ok_or_else(invalid)?;
+        let entry = match kind {
+            "?" | "!" => StatusEntry {
+                kind: if kind == "?" {
+                    EntryKind::Untracked
+                } else {
+                    EntryKind::Ignored
+                },
+                index: '?',
+                worktree: '?',
+                path: rest.to_string(),
+            },
+            // `1 XY sub mH mI mW hH hI path`, `u XY sub m1 m2 m3 mW h1 h2 h3 path`
+            // and `2 XY sub mH mI mW hH hI Xscore path`, followed by the original path.
+            "1" | "2" | "u" => {
+                let fields = match kind {
+                    "1" => 8,
+                    "2" => 9,
+                    _ => 10,
+                };
+                let mut parts = rest.splitn(fields, ' ');
+                let mut xy = parts.next().ok_or_else(invalid)?.chars();
+                let (index, worktree) = (
+                    xy.next().ok_or_else(invalid)?,
+                    xy.next().ok_or_else(invalid)?,
+                );
+                let path = parts.nth(fields - 2).ok_or_else(invalid)?.to_string();
+                let kind = match kind {
+                    "1" => EntryKind::Changed,
+                    "2" => EntryKind::Renamed {
+                        from: records.next().ok_or_else(invalid)?.to_string(),
+                    },
+                    _ => EntryKind::Unmerged,
+                };
+                StatusEntry {
+                    kind,
+                    index,
+                    worktree,
+                    path,
+                }
+            }
+            _ => return Err(invalid()),
+        };
+        status.entries.push(entry);
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "3a8e9f4c1f0d2b7a6e5c4d3b2a1f0e9d8c7b6a5f";
+    const B: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn test_parse_log() {
+        let output = format!(
+            "{a}\x1f{b}\x1fOctocat\x1foctocat@github.com\x1fFix the frobnicator\x1fIt was broken.\n\nSigned-off-by: Octocat <octocat@github.com>\nFixes: #12\n\x1fSigned-off-by: Octocat <octocat@github.com>\nFixes: #12\n\x1e\n{b}\x1f\x1fHubot\x1fhubot@github.com\x1fInitial commit\x1f\x1f\x1e",
+            a = A,
+            b = B
+        );
+        let commits = parse_log(&output).unwrap();
+        assert_eq!(
+            commits,
+            vec![
+                Commit {
+                    id: A.parse().unwrap(),
+                    parents: vec![B.parse().unwrap()],
+                    author_name: "Octocat".to_string(),
+                    author_email: "octocat@github.com".to_string(),
+                    subject: "Fix the frobnicator".to_string(),
+                    body:
+                        "It was broken.\n\nSigned-off-by: Octocat <octocat@github.com>\nFixes: #12"
+                            .to_string(),
+                    trailers: vec![
+                        (
+                            "Signed-off-by".to_string(),
+                            "Octocat <octocat@github.com>".to_string()
+                        ),
+                        ("Fixes".to_string(), "#12".to_string()),
+                    ],
+                },
+                Commit {
+                    id: B.parse().unwrap(),
+                    parents: vec![],
+                    author_name: "Hubot".to_string(),
+                    author_email: "hubot@github.com".to_string(),
+                    subject: "Initial commit".to_string(),
+                    body: String::new(),
+                    trailers: vec![],
+                },
+            ]
+        );
+        assert_eq!(commits[0].trailer("fixes").collect::<Vec<_>>(), ["#12"]);
+        assert!(parse_log("").unwrap().is_empty());
+        assert!(parse_log("not a commit\x1e").is_err());
+    }
+
+    #[test]
+    fn test_parse_name_status() {
+        assert_eq!(
+            parse_name_status(
+                "M\0src/lib.rs\0R087\0old name.rs\0new name.rs\0A\0docs/a.md\0D\0b\0"
+            )
+            .unwrap(),
+            vec![
+                FileChange {
+                    kind: ChangeKind::Modified,
+                    path: "src/lib.rs".to_string(),
+                },
+                FileChange {
+                    kind: ChangeKind::Renamed {
+                        from: "old name.rs".to_string(),
+                        score: 87,
+                    },
+                    path: "new name.rs".to_string(),
+                },
+                FileChange {
+                    kind: ChangeKind::Added,
+                    path: "docs/a.md".to_string(),
+                },
+                FileChange {
+                    kind: ChangeKind::Deleted,
+                    path: "b".to_string(),
+                },
+            ]
+        );
+        assert!(parse_name_status("M\0").is_err());
+        assert!(parse_name_status("Z\0a\0").is_err());
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output = format!(
+            "# branch.oid {a}\0# branch.head cherry-pick-1-to-release\0# branch.upstream origin/release\0# branch.ab +2 -0\0\
+             1 .M N... 100644 100644 100644 {a} {a} src/lib.rs\0\
+             2 R. N... 100644 100644 100644 {a} {a} R100 new name.rs\0old name.rs\0\
+             u UU N... 100644 100644 100644 100644 {a} {b} {a} src/main.rs\0\
+             ? target/out file\0",
+            a = A,
+            b = B
+        );
+        let status = parse_status(&output).unwrap();
+        assert_eq!(
+            status,
+            Status {
+                oid: Some(A.parse().unwrap()),
+                branch: Some("cherry-pick-1-to-release".to_string()),
+                upstream: Some("origin/release".to_string()),
+                ahead_behind: Some((2, 0)),
+                entries: vec![
+                    StatusEntry {
+                        kind: EntryKind::Changed,
+                        index: '.',
+                        worktree: 'M',
+                        path: "src/lib.rs".to_string(),
+                    },
+                    StatusEntry {
+                        kind: EntryKind::Renamed {
+                            from: "old name.rs".to_string(),
+                        },
+                        index: 'R',
+                        worktree: '.',
+                        path: "new name.rs".to_string(),
+                    },
+                    StatusEntry {
+                        kind: EntryKind::Unmerged,
+                        index: 'U',
+                        worktree: 'U',
+                        path: "src/main.rs".to_string(),
+                    },
+                    StatusEntry {
+                        kind: EntryKind::Untracked,
+                        index: '?',
+                        worktree: '?',
+                        path: "target/out file".to_string(),
+                    },
+                ],
+            }
+        );
+        assert!(!status.is_clean());
+        assert_eq!(status.conflicts().collect::<Vec<_>>(), ["src/main.rs"]);
+
+        let status =
+            parse_status("# branch.oid (initial)\0# branch.head (detached)\0? a\0").unwrap();
+        assert_eq!(status.oid, None);
+        assert_eq!(status.branch, None);
+        assert!(status.is_clean());
+    }
+}
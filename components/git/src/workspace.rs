@@ -0,0 +1,78 @@
+use std::{
+    io,
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use logit::Logit;
+use tempfile::TempDir;
+
+use crate::{Git, GitBuilder};
+
+/// A repository in a new temporary directory, so that concurrent picks never share a
+/// working tree and a failed one leaves nothing behind for the next.
+///
+/// The directory is removed when the workspace is dropped. Prefer `close`, which reports
+/// the errors of `Git::clean`, once done with it.
+pub struct Workspace {
+    git: Git,
+    // Removes the directory on drop, unless `close` already did.
+    dir: Option<TempDir>,
+}
+
+impl Workspace {
+    /// Creates the directory of the workspace under the temporary directory, building
+    /// the repository in an empty `repo` directory in it with `builder`.
+    pub fn new(builder: GitBuilder) -> io::Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("{}-{}-", builder.owner, builder.repo))
+            .tempdir()?;
+        let git = builder.dir(dir.path().join("repo")).build();
+        Ok(Self {
+            git,
+            dir: Some(dir),
+        })
+    }
+
+    /// Returns the path of the file `name` in the workspace, outside of the repository,
+    /// e.g. for patches.
+    pub fn scratch_path(&self, name: impl AsRef<str>) -> PathBuf {
+        self.dir
+            .as_ref()
+            .expect("the workspace is not closed")
+            .path()
+            .join(name.as_ref())
+    }
+
+    /// Removes the workspace.
+    pub async fn close(mut self) -> Result<()> {
+        self.git.clean().await?;
+        if let Some(dir) = self.dir.take() {
+            dir.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for Workspace {
+    type Target = Git;
+
+    fn deref(&self) -> &Git {
+        &self.git
+    }
+}
+
+impl DerefMut for Workspace {
+    fn deref_mut(&mut self) -> &mut Git {
+        &mut self.git
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if let Some(dir) = self.dir.take() {
+            let _ = dir.close().logit_warn("remove workspace");
+        }
+    }
+}
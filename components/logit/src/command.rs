@@ -0,0 +1,76 @@
+use std::fmt;
+
+/// A workflow command, written to stdout for the runner to pick up.
+///
+/// [See the GitHub documentation](https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions)
+pub struct WorkflowCommand {
+    command: &'static str,
+    properties: Vec<(&'static str, String)>,
+    message: String,
+}
+
+impl WorkflowCommand {
+    pub fn new(command: &'static str, message: impl fmt::Display) -> Self {
+        Self {
+            command,
+            properties: Vec::new(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn property(mut self, key: &'static str, value: impl fmt::Display) -> Self {
+        self.properties.push((key, value.to_string()));
+        self
+    }
+
+    pub fn issue(self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for WorkflowCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "::{}", self.command)?;
+        for (i, (key, value)) in self.properties.iter().enumerate() {
+            let sep = if i == 0 { ' ' } else { ',' };
+            write!(f, "{}{}={}", sep, key, escape_property(value))?;
+        }
+        write!(f, "::{}", escape_data(&self.message))
+    }
+}
+
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkflowCommand;
+
+    #[test]
+    fn test_workflow_command() {
+        let cases = vec![
+            (WorkflowCommand::new("endgroup", ""), "::endgroup::"),
+            (
+                WorkflowCommand::new("debug", "100% done\r\nnext"),
+                "::debug::100%25 done%0D%0Anext",
+            ),
+            (
+                WorkflowCommand::new("error", "failed: a, b")
+                    .property("file", "src/a:b,c.rs")
+                    .property("line", 12),
+                "::error file=src/a%3Ab%2Cc.rs,line=12::failed: a, b",
+            ),
+        ];
+
+        for (cmd, expected) in cases {
+            assert_eq!(cmd.to_string(), expected);
+        }
+    }
+}
@@ -1,5 +1,11 @@
 use std::fmt;
 
+pub use command::WorkflowCommand;
+pub use subscriber::{init, subscriber, ActionsLayer};
+
+mod command;
+mod subscriber;
+
 pub trait Logit<T, E: fmt::Debug> {
     fn logit(self, msg: impl fmt::Display) -> Result<T, E>;
     fn with_logit<F, C>(self, f: F) -> Result<T, E>
@@ -0,0 +1,212 @@
+use std::{env, fmt, io::Write};
+
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::MakeWriter,
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    Layer, Registry,
+};
+
+use crate::WorkflowCommand;
+
+/// Returns a subscriber suited to where we run: workflow commands under GitHub Actions,
+/// so that errors and warnings become annotations on the run page, and pretty-printed
+/// logs otherwise.
+pub fn subscriber() -> Box<dyn Subscriber + Send + Sync> {
+    if env::var("GITHUB_ACTIONS").as_deref() == Ok("true") {
+        // Debug messages are only shown when step debug logging is enabled.
+        let level = if env::var("RUNNER_DEBUG").as_deref() == Ok("1") {
+            LevelFilter::DEBUG
+        } else {
+            LevelFilter::INFO
+        };
+        Box::new(Registry::default().with(ActionsLayer::new(std::io::stdout).with_filter(level)))
+    } else {
+        Box::new(
+            tracing_subscriber::fmt()
+                .pretty()
+                .with_max_level(LevelFilter::INFO)
+                .finish(),
+        )
+    }
+}
+
+/// Installs `subscriber()` as the global default.
+pub fn init() {
+    let _ = tracing::subscriber::set_global_default(subscriber());
+}
+
+/// A layer writing events as GitHub Actions workflow commands: `::error::` for
+/// errors, `::warning::` for warnings, `::debug::` for debug and trace events
+/// and plain lines for the rest. Fields of the enclosing spans are appended.
+pub struct ActionsLayer<W> {
+    make_writer: W,
+}
+
+impl<W> ActionsLayer<W>
+where
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    pub fn new(make_writer: W) -> Self {
+        Self { make_writer }
+    }
+}
+
+/// The formatted fields of a span, stored in its extensions.
+struct SpanFields(String);
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.record_debug(field, &format_args!("{}", value));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            if !self.fields.is_empty() {
+                self.fields.push(' ');
+            }
+            self.fields
+                .push_str(&format!("{}={:?}", field.name(), value));
+        }
+    }
+}
+
+impl<S, W> Layer<S> for ActionsLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + 'static,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut extensions = span.extensions_mut();
+        let mut visitor = FieldVisitor::default();
+        if let Some(SpanFields(fields)) = extensions.remove::<SpanFields>() {
+            visitor.fields = fields;
+        }
+        values.record(&mut visitor);
+        extensions.insert(SpanFields(visitor.fields));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let mut line = visitor.message;
+        if !visitor.fields.is_empty() {
+            line.push_str(&format!(" {}", visitor.fields));
+        }
+        if let Some(scope) = ctx.event_scope(event) {
+            let spans = scope
+                .from_root()
+                .filter_map(|span| {
+                    span.extensions()
+                        .get::<SpanFields>()
+                        .filter(|SpanFields(fields)| !fields.is_empty())
+                        .map(|SpanFields(fields)| format!("{}{{{}}}", span.name(), fields))
+                })
+                .collect::<Vec<_>>();
+            if !spans.is_empty() {
+                line.push_str(&format!(" [{}]", spans.join(" ")));
+            }
+        }
+
+        let command = match *event.metadata().level() {
+            Level::ERROR => Some("error"),
+            Level::WARN => Some("warning"),
+            Level::INFO => None,
+            Level::DEBUG | Level::TRACE => Some("debug"),
+        };
+        let mut writer = self.make_writer.make_writer();
+        let _ = match command {
+            Some(command) => writeln!(writer, "{}", WorkflowCommand::new(command, line)),
+            None => writeln!(writer, "{}", line),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, Registry};
+
+    use super::ActionsLayer;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_actions_layer() {
+        let buffer = Buffer::default();
+        let subscriber = Registry::default().with(ActionsLayer::new(buffer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "cherry_pick",
+                pull_number = 12,
+                target_branch = "release-1.2"
+            );
+            let _enter = span.enter();
+            tracing::info!("Applying patch");
+            tracing::warn!(error = "timeout", "Error getting bot repository.");
+            tracing::error!("apply #12\non top of release-1.2: 100% conflict");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "Applying patch [cherry_pick{pull_number=12 target_branch=release-1.2}]\n\
+             ::warning::Error getting bot repository. error=timeout [cherry_pick{pull_number=12 target_branch=release-1.2}]\n\
+             ::error::apply #12%0Aon top of release-1.2: 100%25 conflict [cherry_pick{pull_number=12 target_branch=release-1.2}]\n"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::config,
+    github::{Backport, BackportState},
+    util::{match_label, match_picked_label, pending_targets},
+};
+
+/// Where the pick onto a target branch takes its changes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// The original pull request.
+    Original,
+    /// The merged backport with this number.
+    Backport(u64),
+    /// Nothing yet, the backport onto this earlier branch of the chain is not merged yet.
+    Wait(String),
+}
+
+/// Returns the branch of `chain` right before `target_branch` among the `requested` ones.
+fn predecessor<'a>(
+    chain: &'a [String],
+    requested: &[String],
+    target_branch: &str,
+) -> Option<&'a str> {
+    let position = chain.iter().position(|branch| branch == target_branch)?;
+    chain[..position]
+        .iter()
+        .rev()
+        .find(|branch| requested.contains(branch))
+        .map(|branch| branch.as_str())
+}
+
+/// Orders `targets` along `chain_backports` and decides where each pick takes its changes
+/// from, given the `labels` of the pull request and its `backports` by target branch.
+///
+/// A target picks from the merged backport onto its predecessor, the nearest earlier branch
+/// of the chain that was requested too. It waits while that backport is open or not even
+/// picked yet, and picks from the original pull request once it was closed unmerged or
+/// failed. Targets outside of the chain pick from the original, after the chained ones.
+pub fn plan(
+    labels: &[String],
+    mut targets: Vec<String>,
+    backports: &HashMap<String, Backport>,
+) -> Vec<(String, Source)> {
+    let chain = &config().chain_backports;
+    let requested = labels
+        .iter()
+        .filter_map(|label| match_label(label).or_else(|| match_picked_label(label)))
+        .collect::<Vec<_>>();
+    let (_, pending) = pending_targets(labels);
+
+    targets.sort_by_key(|target| {
+        chain
+            .iter()
+            .position(|branch| branch == target)
+            .unwrap_or(usize::MAX)
+    });
+    targets
+        .into_iter()
+        .map(|target| {
+            let source = match predecessor(chain, &requested, &target) {
+                None => Source::Original,
+                Some(previous) => match backports.get(previous) {
+                    Some(Backport {
+                        number,
+                        state: BackportState::Merged,
+                    }) => Source::Backport(*number),
+                    Some(Backport {
+                        state: BackportState::Open,
+                        ..
+                    }) => Source::Wait(previous.to_string()),
+                    Some(Backport {
+                        state: BackportState::Closed,
+                        ..
+                    }) => Source::Original,
+                    None if pending.iter().any(|branch| branch == previous) => {
+                        Source::Wait(previous.to_string())
+                    }
+                    None => Source::Original,
+                },
+            };
+            (target, source)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::{lock_config, replace_config, Config};
+
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_predecessor() {
+        let chain = strings(&["2.0", "1.9", "1.8"]);
+        let cases = vec![
+            (vec!["2.0", "1.9", "1.8"], "1.8", Some("1.9")),
+            (vec!["2.0", "1.8"], "1.8", Some("2.0")),
+            (vec!["2.0", "1.9"], "2.0", None),
+            (vec!["1.9", "1.8"], "1.7", None),
+            (vec!["1.8"], "1.8", None),
+        ];
+        for (requested, target, expected) in cases {
+            assert_eq!(
+                predecessor(&chain, &strings(&requested), target),
+                expected,
+                "requested: {:?}, target: {}",
+                requested,
+                target
+            );
+        }
+    }
+
+    /// Drives the plan the way the sweep does, from the pending targets of the labels.
+    #[test]
+    fn test_plan_sweep() {
+        let _lock = lock_config();
+        let mut c = Config::from_actions().unwrap();
+        c.chain_backports = strings(&["2.0", "1.9", "1.8"]);
+        replace_config(c);
+
+        let backport = |number, state| Backport { number, state };
+        let cases = vec![
+            (
+                // Nothing picked yet, only the head of the chain goes now.
+                vec![
+                    "needs-cherry-pick/1.8",
+                    "needs-cherry-pick/2.0",
+                    "needs-cherry-pick/1.9",
+                ],
+                vec![],
+                vec![
+                    ("2.0", Source::Original),
+                    ("1.9", Source::Wait("2.0".to_string())),
+                    ("1.8", Source::Wait("1.9".to_string())),
+                ],
+            ),
+            (
+                // The backport onto 2.0 is open.
+                vec![
+                    "needs-cherry-pick/2.0",
+                    "cherry-picked/2.0",
+                    "needs-cherry-pick/1.9",
+                ],
+                vec![("2.0", backport(10, BackportState::Open))],
+                vec![("1.9", Source::Wait("2.0".to_string()))],
+            ),
+            (
+                // The backport onto 2.0 was merged, 1.8 skips over the unrequested 1.9.
+                vec![
+                    "needs-cherry-pick/2.0",
+                    "cherry-picked/2.0",
+                    "needs-cherry-pick/1.8",
+                ],
+                vec![("2.0", backport(10, BackportState::Merged))],
+                vec![("1.8", Source::Backport(10))],
+            ),
+            (
+                // The backport onto 2.0 was closed unmerged.
+                vec![
+                    "needs-cherry-pick/2.0",
+                    "cherry-picked/2.0",
+                    "needs-cherry-pick/1.9",
+                ],
+                vec![("2.0", backport(10, BackportState::Closed))],
+                vec![("1.9", Source::Original)],
+            ),
+            (
+                // The pick onto 2.0 failed, 1.9 does not wait for its retry.
+                vec![
+                    "needs-cherry-pick/2.0",
+                    "cherry-pick-failed/2.0",
+                    "needs-cherry-pick/1.9",
+                    "needs-cherry-pick/main",
+                ],
+                vec![],
+                vec![("1.9", Source::Original), ("main", Source::Original)],
+            ),
+        ];
+        for (labels, backports, expected) in cases {
+            let labels = strings(&labels);
+            let backports = backports
+                .into_iter()
+                .map(|(branch, backport)| (branch.to_string(), backport))
+                .collect::<HashMap<_, _>>();
+            let (_, targets) = pending_targets(&labels);
+            let expected = expected
+                .into_iter()
+                .map(|(branch, source)| (branch.to_string(), source))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                plan(&labels, targets, &backports),
+                expected,
+                "labels: {:?}",
+                labels
+            );
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::{env, net::SocketAddr, path::PathBuf, process, str::FromStr};
+
+use actions::RepoRef;
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+
+use crate::{
+    apply_backport, cherry_pick,
+    commands::{PickOptions, Strategy},
+    config::{self, Config},
+    github::{authenticate, forking_user, get_pull, init_auth, init_endpoint, App, Auth, Endpoint},
+    server,
+};
+
+pub const USAGE: &str = "\
+Usage: cherrypicker-action pick --repo <owner/repo> --pr <number> --to <branch>...
+                               [--fork <user>] [--strategy am|cherry-pick] [--draft] [--dry-run]
+       cherrypicker-action serve [--addr <addr>] [--queue-size <size>] [--cache-dir <dir>]
+
+Talks to the GitHub instance at GITHUB_SERVER_URL, github.com by default.
+Authenticates as the GitHub App GITHUB_APP_ID with the private key GITHUB_APP_PRIVATE_KEY,
+or else with the token from GH_TOKEN or GITHUB_TOKEN, or else from `gh auth token`.
+The inputs of the action are read from the INPUT_* variables, e.g. INPUT_LABEL-PREFIX.
+
+pick: Cherry-picks a pull request onto the target branches, exactly like the action does.
+    --repo <owner/repo>     Repository of the pull request
+    --pr <number>           Number of the pull request
+    --to <branch>           Target branch, may be repeated
+    --fork <user>           User to push the backport branches to, defaults to the token's user,
+                            or the owner of the repository for a GitHub App
+    --strategy <strategy>   How the changes are applied, `am` (default) or `cherry-pick`
+    --draft                 Open the backport pull requests as drafts
+    --dry-run               Only apply the changes in a local clone, without pushing
+
+serve: Handles webhook deliveries, signed with the secret in WEBHOOK_SECRET.
+    --addr <addr>           Address to listen on, defaults to 0.0.0.0:8080
+    --queue-size <size>     Deliveries waiting to be handled before new ones are refused,
+                            defaults to 100
+    --cache-dir <dir>       Directory keeping a mirror of each repository across deliveries,
+                            defaults to INPUT_CACHE-DIR, or else cherrypicker-action in the
+                            temporary directory
+";
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    #[error("unknown subcommand `{0}`")]
+    UnknownSubcommand(String),
+    #[error("unexpected argument `{0}`")]
+    UnexpectedArgument(String),
+    #[error("missing value for `{0}`")]
+    MissingValue(String),
+    #[error("invalid value `{value}` for `{flag}`")]
+    InvalidValue { flag: String, value: String },
+    #[error("missing required option `{0}`")]
+    MissingOption(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    Pick(PickArgs),
+    Serve(ServeArgs),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PickArgs {
+    pub repo: RepoRef,
+    pub pull_number: u64,
+    pub targets: Vec<String>,
+    pub fork: Option<String>,
+    pub options: PickOptions,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServeArgs {
+    pub addr: SocketAddr,
+    pub queue_size: usize,
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Command line arguments, where both `--flag value` and `--flag=value` are accepted.
+struct Args<I>(I);
+
+impl<I: Iterator<Item = String>> Args<I> {
+    /// Returns the next flag and its inline value, if any.
+    fn next_flag(&mut self) -> Option<(String, Option<String>)> {
+        let arg = self.0.next()?;
+        Some(match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        })
+    }
+
+    fn value(&mut self, flag: &str, inline_value: Option<String>) -> Result<String, CliError> {
+        inline_value
+            .or_else(|| self.0.next())
+            .ok_or_else(|| CliError::MissingValue(flag.to_string()))
+    }
+
+    fn parse<T: FromStr>(
+        &mut self,
+        flag: &str,
+        inline_value: Option<String>,
+    ) -> Result<T, CliError> {
+        let value = self.value(flag, inline_value)?;
+        value.parse().map_err(|_| CliError::InvalidValue {
+            flag: flag.to_string(),
+            value,
+        })
+    }
+}
+
+fn unexpected(flag: String, inline_value: Option<String>) -> CliError {
+    match inline_value {
+        Some(value) => CliError::UnexpectedArgument(format!("{}={}", flag, value)),
+        None => CliError::UnexpectedArgument(flag),
+    }
+}
+
+/// Parses the command line arguments, without the program name.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<CliCommand, CliError> {
+    let mut args = Args(args.into_iter());
+    match args.0.next().as_deref() {
+        Some("pick") => parse_pick(args),
+        Some("serve") => parse_serve(args),
+        None | Some("help" | "--help" | "-h") => Ok(CliCommand::Help),
+        Some(x) => Err(CliError::UnknownSubcommand(x.to_string())),
+    }
+}
+
+fn parse_pick(mut args: Args<impl Iterator<Item = String>>) -> Result<CliCommand, CliError> {
+    let mut repo = None;
+    let mut pull_number = None;
+    let mut targets = Vec::new();
+    let mut fork = None;
+    let mut options = PickOptions::default();
+    let mut dry_run = false;
+    while let Some((flag, inline_value)) = args.next_flag() {
+        match (flag.as_str(), &inline_value) {
+            ("--repo", _) => {
+                let value = args.value(&flag, inline_value)?;
+                repo = match value.split_once('/') {
+                    Some((owner, name))
+                        if !owner.is_empty() && !name.is_empty() && !name.contains('/') =>
+                    {
+                        Some(RepoRef {
+                            owner: owner.to_string(),
+                            repo: name.to_string(),
+                        })
+                    }
+                    _ => return Err(CliError::InvalidValue { flag, value }),
+                };
+            }
+            ("--pr", _) => {
+                let value = args.value(&flag, inline_value)?;
+                pull_number = match value.trim_start_matches('#').parse() {
+                    Ok(x) => Some(x),
+                    Err(_) => return Err(CliError::InvalidValue { flag, value }),
+                };
+            }
+            ("--to", _) => targets.push(args.value(&flag, inline_value)?),
+            ("--fork", _) => fork = Some(args.value(&flag, inline_value)?),
+            ("--strategy", _) => options.strategy = args.parse::<Strategy>(&flag, inline_value)?,
+            ("--draft", None) => options.draft = true,
+            ("--dry-run", None) => dry_run = true,
+            ("--help" | "-h", None) => return Ok(CliCommand::Help),
+            _ => return Err(unexpected(flag, inline_value)),
+        }
+    }
+
+    if targets.is_empty() {
+        return Err(CliError::MissingOption("--to"));
+    }
+    Ok(CliCommand::Pick(PickArgs {
+        repo: repo.ok_or(CliError::MissingOption("--repo"))?,
+        pull_number: pull_number.ok_or(CliError::MissingOption("--pr"))?,
+        targets: targets.into_iter().unique().collect(),
+        fork,
+        options,
+        dry_run,
+    }))
+}
+
+fn parse_serve(mut args: Args<impl Iterator<Item = String>>) -> Result<CliCommand, CliError> {
+    let mut serve = ServeArgs {
+        addr: ([0, 0, 0, 0], 8080).into(),
+        queue_size: 100,
+        cache_dir: None,
+    };
+    while let Some((flag, inline_value)) = args.next_flag() {
+        match (flag.as_str(), &inline_value) {
+            ("--addr", _) => serve.addr = args.parse(&flag, inline_value)?,
+            ("--queue-size", _) => {
+                serve.queue_size = args.parse(&flag, inline_value)?;
+                if serve.queue_size == 0 {
+                    return Err(CliError::InvalidValue {
+                        flag,
+                        value: "0".to_string(),
+                    });
+                }
+            }
+            ("--cache-dir", _) => {
+                serve.cache_dir = Some(args.value(&flag, inline_value)?.into());
+            }
+            ("--help" | "-h", None) => return Ok(CliCommand::Help),
+            _ => return Err(unexpected(flag, inline_value)),
+        }
+    }
+    Ok(CliCommand::Serve(serve))
+}
+
+/// Returns the GitHub App from the environment, or else a token.
+fn auth() -> Result<Auth> {
+    let app_id = match env::var("GITHUB_APP_ID") {
+        Ok(app_id) => app_id,
+        Err(_) => return token().map(Auth::Token),
+    };
+    let app_id = app_id
+        .parse()
+        .with_context(|| format!("invalid GITHUB_APP_ID {:?}", app_id))?;
+    let private_key =
+        env::var("GITHUB_APP_PRIVATE_KEY").context("GITHUB_APP_PRIVATE_KEY is not set")?;
+    Ok(Auth::App(App::new(app_id, private_key)?))
+}
+
+/// Returns the token from the environment, or else from the `gh` config.
+fn token() -> Result<String> {
+    if let Some(token) = ["GH_TOKEN", "GITHUB_TOKEN"]
+        .iter()
+        .find_map(|k| env::var(k).ok().filter(|x| !x.is_empty()))
+    {
+        return Ok(token);
+    }
+
+    let output = process::Command::new("gh")
+        .args(["auth", "token"])
+        .output()
+        .context("run `gh auth token`, set GH_TOKEN or GITHUB_TOKEN instead")?;
+    if !output.status.success() {
+        bail!(
+            "`gh auth token` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)
+        .context("decode `gh auth token` output")?
+        .trim()
+        .to_string())
+}
+
+/// Runs the command line interface with `args`, without the program name.
+pub async fn run(args: impl IntoIterator<Item = String>) -> Result<()> {
+    let command = parse_args(args)?;
+    if command == CliCommand::Help {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    let mut config = Config::from_actions().context("read inputs")?;
+    if let CliCommand::Serve(args) = &command {
+        // Every delivery would clone the repository again otherwise.
+        config.cache_dir = args
+            .cache_dir
+            .clone()
+            .or(config.cache_dir)
+            .or_else(|| Some(env::temp_dir().join("cherrypicker-action")));
+    }
+    config::init_config(config);
+    init_endpoint(Endpoint::from_env());
+    init_auth(auth()?);
+    match command {
+        CliCommand::Pick(args) => pick(args).await,
+        CliCommand::Serve(args) => {
+            let secret = env::var("WEBHOOK_SECRET")
+                .ok()
+                .filter(|x| !x.is_empty())
+                .context("WEBHOOK_SECRET is not set")?;
+            server::serve(args.addr, secret, args.queue_size).await
+        }
+        CliCommand::Help => Ok(()),
+    }
+}
+
+async fn pick(args: PickArgs) -> Result<()> {
+    let RepoRef { owner, repo } = &args.repo;
+    authenticate(owner, repo).await?;
+    let pr = get_pull(owner, repo, args.pull_number).await?;
+    if pr.merged_at.is_none() {
+        bail!("pull request #{} is not merged", pr.number);
+    }
+    let forking_user = match args.fork {
+        Some(user) => user,
+        None => forking_user(owner).await?,
+    };
+
+    let mut failed = Vec::new();
+    for target_branch in &args.targets {
+        authenticate(owner, repo).await?;
+        let picked = if args.dry_run {
+            apply_backport(
+                owner,
+                repo,
+                pr.number,
+                pr.number,
+                target_branch,
+                &args.options,
+            )
+            .await
+            .map(|(_, local_branch)| format!("applied cleanly on {}, not pushed", local_branch))
+        } else {
+            cherry_pick(
+                &forking_user,
+                owner,
+                repo,
+                pr.number,
+                pr.number,
+                target_branch,
+                &args.options,
+            )
+            .await
+            .map(|backport| match backport.html_url {
+                Some(url) => format!("picked as {}", url),
+                None => format!("picked as #{}", backport.number),
+            })
+        };
+        match picked {
+            Ok(message) => println!("{}: {}", target_branch, message),
+            Err(err) => {
+                println!("{}: failed: {:#}", target_branch, err);
+                failed.push(target_branch.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        bail!(
+            "failed to cherry-pick #{} to {}",
+            pr.number,
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn parse(args: &str) -> Result<CliCommand, CliError> {
+        parse_args(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse("pick --repo 0x5459/cherrypicker-action --pr #123 --to release-1.2 --to=release-1.3 --strategy cherry-pick --draft --dry-run --fork lbw"),
+            Ok(CliCommand::Pick(PickArgs {
+                repo: RepoRef {
+                    owner: "0x5459".to_string(),
+                    repo: "cherrypicker-action".to_string(),
+                },
+                pull_number: 123,
+                targets: vec!["release-1.2".to_string(), "release-1.3".to_string()],
+                fork: Some("lbw".to_string()),
+                options: PickOptions {
+                    strategy: Strategy::CherryPick,
+                    draft: true,
+                },
+                dry_run: true,
+            }))
+        );
+
+        let cases = vec![
+            ("", Ok(CliCommand::Help)),
+            ("--help", Ok(CliCommand::Help)),
+            ("pick --help", Ok(CliCommand::Help)),
+            ("push", Err(CliError::UnknownSubcommand("push".to_string()))),
+            (
+                "pick --repo o/r --pr 1",
+                Err(CliError::MissingOption("--to")),
+            ),
+            ("pick --pr 1 --to b", Err(CliError::MissingOption("--repo"))),
+            (
+                "pick --repo o/r --to b",
+                Err(CliError::MissingOption("--pr")),
+            ),
+            (
+                "pick --repo o/r --pr",
+                Err(CliError::MissingValue("--pr".to_string())),
+            ),
+            (
+                "pick --repo o --pr 1 --to b",
+                Err(CliError::InvalidValue {
+                    flag: "--repo".to_string(),
+                    value: "o".to_string(),
+                }),
+            ),
+            (
+                "pick --repo o/r --pr one --to b",
+                Err(CliError::InvalidValue {
+                    flag: "--pr".to_string(),
+                    value: "one".to_string(),
+                }),
+            ),
+            (
+                "pick --repo o/r --pr 1 --to b --strategy=rebase",
+                Err(CliError::InvalidValue {
+                    flag: "--strategy".to_string(),
+                    value: "rebase".to_string(),
+                }),
+            ),
+            (
+                "pick --repo o/r --pr 1 --to b --draft=true",
+                Err(CliError::UnexpectedArgument("--draft=true".to_string())),
+            ),
+            (
+                "pick --repo o/r --pr 1 b",
+                Err(CliError::UnexpectedArgument("b".to_string())),
+            ),
+            (
+                "serve",
+                Ok(CliCommand::Serve(ServeArgs {
+                    addr: ([0, 0, 0, 0], 8080).into(),
+                    queue_size: 100,
+                    cache_dir: None,
+                })),
+            ),
+            (
+                "serve --addr 127.0.0.1:3000 --queue-size=10 --cache-dir /var/cache/picks",
+                Ok(CliCommand::Serve(ServeArgs {
+                    addr: ([127, 0, 0, 1], 3000).into(),
+                    queue_size: 10,
+                    cache_dir: Some("/var/cache/picks".into()),
+                })),
+            ),
+            (
+                "serve --addr localhost",
+                Err(CliError::InvalidValue {
+                    flag: "--addr".to_string(),
+                    value: "localhost".to_string(),
+                }),
+            ),
+            (
+                "serve --queue-size 0",
+                Err(CliError::InvalidValue {
+                    flag: "--queue-size".to_string(),
+                    value: "0".to_string(),
+                }),
+            ),
+        ];
+        for (args, expected) in cases {
+            assert_eq!(parse(args), expected, "args: {}", args);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use std::str::FromStr;
+
+use itertools::Itertools;
+
+/// A slash command found in a comment.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Command {
+    /// `/cherry-pick ...` or `/cherrypick ...`
+    CherryPick(CherryPickCommand),
+    /// `/cherry-pick-invite` or `/cherrypick-invite`
+    Invite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CherryPickCommand {
+    /// `/cherry-pick [--strategy=<strategy>] [--draft] <branch>`
+    Pick {
+        target_branch: String,
+        options: PickOptions,
+    },
+    /// `/cherry-pick retry [--strategy=<strategy>] [--draft] <branch>`
+    Retry {
+        target_branch: String,
+        options: PickOptions,
+    },
+    /// `/cherry-pick cancel <branch>`
+    Cancel { target_branch: String },
+    /// `/cherry-pick status`
+    Status,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PickOptions {
+    pub strategy: Strategy,
+    // Specifies whether the backport pull request is opened as a draft.
+    pub draft: bool,
+}
+
+/// How the changes of a pull request are applied onto the target branch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Applies the patch of the pull request with `git am --3way`.
+    #[default]
+    Am,
+    /// Cherry-picks each commit of the pull request, without the merges of the base branch,
+    /// recording where it was picked from.
+    CherryPick,
+}
+
+impl FromStr for Strategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "am" => Ok(Self::Am),
+            "cherry-pick" => Ok(Self::CherryPick),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CommandError {
+    #[error("unknown command `{0}`")]
+    UnknownCommand(String),
+    #[error("missing target branch for `{0}`")]
+    MissingBranch(String),
+    #[error("invalid target branch `{0}`")]
+    InvalidBranch(String),
+    #[error("unexpected argument `{0}`")]
+    UnexpectedArgument(String),
+    #[error("unknown flag `{0}`")]
+    UnknownFlag(String),
+    #[error("invalid value `{value}` for flag `--{flag}`")]
+    InvalidFlagValue { flag: String, value: String },
+    #[error("unterminated quote in `{0}`")]
+    UnterminatedQuote(String),
+}
+
+const CHERRY_PICK_COMMANDS: [&str; 2] = ["/cherry-pick", "/cherrypick"];
+const INVITE_COMMANDS: [&str; 2] = ["/cherry-pick-invite", "/cherrypick-invite"];
+// The first argument of a cherry-pick command naming one of these is not a branch.
+const SUBCOMMANDS: [&str; 3] = ["retry", "cancel", "status"];
+
+/// Parses the commands of a comment, one per line. Lines inside fenced code blocks
+/// and quoted replies are ignored, so are duplicated commands.
+pub fn parse_commands(text: impl AsRef<str>) -> Vec<Result<Command, CommandError>> {
+    let mut fence: Option<&str> = None;
+    let mut commands = Vec::new();
+    for line in text.as_ref().lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = Some(marker);
+            continue;
+        }
+        if trimmed.starts_with('>') {
+            continue;
+        }
+
+        let (name, args) = line
+            .trim_end()
+            .split_once(char::is_whitespace)
+            .unwrap_or((line.trim_end(), ""));
+        if CHERRY_PICK_COMMANDS.contains(&name) {
+            commands.push(parse_cherry_pick(name, args).map(Command::CherryPick));
+        } else if INVITE_COMMANDS.contains(&name) {
+            commands.push(Ok(Command::Invite));
+        } else if CHERRY_PICK_COMMANDS.iter().any(|c| name.starts_with(c)) {
+            commands.push(Err(CommandError::UnknownCommand(name.to_string())));
+        }
+    }
+    commands.into_iter().unique().collect()
+}
+
+fn parse_cherry_pick(name: &str, args: &str) -> Result<CherryPickCommand, CommandError> {
+    let mut words = split_args(args)?.into_iter().peekable();
+    let subcommand = match words.peek().map(String::as_str) {
+        Some(word) if SUBCOMMANDS.contains(&word) => {
+            let word = word.to_string();
+            words.next();
+            Some(word)
+        }
+        _ => None,
+    };
+
+    let mut options = PickOptions::default();
+    let mut flags = Vec::new();
+    let mut branches = Vec::new();
+    for word in words {
+        let flag = match word.strip_prefix("--") {
+            Some(flag) => flag,
+            None => {
+                branches.push(word);
+                continue;
+            }
+        };
+        match flag.split_once('=') {
+            Some(("strategy", value)) => {
+                options.strategy = value.parse().map_err(|_| CommandError::InvalidFlagValue {
+                    flag: "strategy".to_string(),
+                    value: value.to_string(),
+                })?
+            }
+            None if flag == "draft" => options.draft = true,
+            _ => return Err(CommandError::UnknownFlag(word)),
+        }
+        flags.push(word);
+    }
+
+    let single_branch = |command: &str| {
+        let mut branches = branches.clone().into_iter();
+        match (branches.next(), branches.next()) {
+            (Some(branch), None) if is_valid_branch(&branch) => Ok(branch),
+            (Some(branch), None) => Err(CommandError::InvalidBranch(branch)),
+            (Some(_), Some(extra)) => Err(CommandError::UnexpectedArgument(extra)),
+            (None, _) => Err(CommandError::MissingBranch(command.to_string())),
+        }
+    };
+    let no_flags = || match flags.first() {
+        Some(flag) => Err(CommandError::UnexpectedArgument(flag.clone())),
+        None => Ok(()),
+    };
+
+    match subcommand.as_deref() {
+        None => Ok(CherryPickCommand::Pick {
+            target_branch: single_branch(name)?,
+            options,
+        }),
+        Some("retry") => Ok(CherryPickCommand::Retry {
+            target_branch: single_branch("retry")?,
+            options,
+        }),
+        Some("cancel") => {
+            no_flags()?;
+            Ok(CherryPickCommand::Cancel {
+                target_branch: single_branch("cancel")?,
+            })
+        }
+        _ => {
+            no_flags()?;
+            match branches.into_iter().next() {
+                Some(extra) => Err(CommandError::UnexpectedArgument(extra)),
+                None => Ok(CherryPickCommand::Status),
+            }
+        }
+    }
+}
+
+/// Splits the arguments of a command on whitespace. A double or single quoted
+/// argument may contain whitespace.
+fn split_args(args: &str) -> Result<Vec<String>, CommandError> {
+    let mut words = Vec::new();
+    let mut chars = args.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut word = String::new();
+        if c == '"' || c == '\'' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some(x) if x == c => break,
+                    Some(x) => word.push(x),
+                    None => return Err(CommandError::UnterminatedQuote(args.trim().to_string())),
+                }
+            }
+        } else {
+            while let Some(x) = chars.next_if(|x| !x.is_whitespace()) {
+                word.push(x);
+            }
+        }
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Returns true if `branch` is a reasonable branch name, following the rules of
+/// `git check-ref-format` but limited to printable ASCII characters and spaces.
+fn is_valid_branch(branch: &str) -> bool {
+    !branch.is_empty()
+        && branch.trim() == branch
+        && branch
+            .chars()
+            .all(|c| (c.is_ascii_graphic() || c == ' ') && !"~^:?*[\\".contains(c))
+        && !branch.starts_with(['-', '/', '.'])
+        && !branch.ends_with(['/', '.'])
+        && !branch.ends_with(".lock")
+        && !branch.contains("..")
+        && !branch.contains("//")
+        && !branch.contains("/.")
+        && !branch.contains("@{")
+        && branch != "@"
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use proptest::{prelude::*, strategy::Strategy as _};
+
+    use super::{Strategy, *};
+
+    fn pick(target_branch: &str) -> Result<Command, CommandError> {
+        Ok(Command::CherryPick(CherryPickCommand::Pick {
+            target_branch: target_branch.to_string(),
+            options: PickOptions::default(),
+        }))
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let cases = vec![
+            ("/cherrypick xx", vec![pick("xx")]),
+            ("/cherry-pick xx", vec![pick("xx")]),
+            ("lbwnb", vec![]),
+            ("see /cherry-pick xx", vec![]),
+            (
+                "/cherrypickxxx",
+                vec![Err(CommandError::UnknownCommand(
+                    "/cherrypickxxx".to_string(),
+                ))],
+            ),
+            (
+                "/cherry-pick-xxx",
+                vec![Err(CommandError::UnknownCommand(
+                    "/cherry-pick-xxx".to_string(),
+                ))],
+            ),
+            (
+                r#"
+/cherry-pick r
+xxxx
+/cherry-pick    releasev0.3
+/cherrypick releasev0.3
+/cherrypick release/v0.5
+        "#,
+                vec![pick("r"), pick("releasev0.3"), pick("release/v0.5")],
+            ),
+            (
+                "/cherrypick release/v0.5😊",
+                vec![Err(CommandError::InvalidBranch(
+                    "release/v0.5😊".to_string(),
+                ))],
+            ),
+            (r#"/cherry-pick "release 1.2""#, vec![pick("release 1.2")]),
+            ("/cherry-pick 'release-1.2'", vec![pick("release-1.2")]),
+            (
+                r#"/cherry-pick "release 1.2"#,
+                vec![Err(CommandError::UnterminatedQuote(
+                    r#""release 1.2"#.to_string(),
+                ))],
+            ),
+            (
+                "```\n/cherry-pick release-1.2\n```\n/cherry-pick release-1.3",
+                vec![pick("release-1.3")],
+            ),
+            (
+                "~~~sh\n/cherry-pick release-1.2\n```\n/cherry-pick release-1.3\n~~~",
+                vec![],
+            ),
+            ("> /cherry-pick release-1.2", vec![]),
+            ("  > /cherry-pick release-1.2", vec![]),
+            (
+                "/cherry-pick --strategy=cherry-pick --draft release-1.2",
+                vec![Ok(Command::CherryPick(CherryPickCommand::Pick {
+                    target_branch: "release-1.2".to_string(),
+                    options: PickOptions {
+                        strategy: Strategy::CherryPick,
+                        draft: true,
+                    },
+                }))],
+            ),
+            (
+                "/cherry-pick retry release-1.2 --strategy=am",
+                vec![Ok(Command::CherryPick(CherryPickCommand::Retry {
+                    target_branch: "release-1.2".to_string(),
+                    options: PickOptions::default(),
+                }))],
+            ),
+            (
+                "/cherrypick cancel release-1.2",
+                vec![Ok(Command::CherryPick(CherryPickCommand::Cancel {
+                    target_branch: "release-1.2".to_string(),
+                }))],
+            ),
+            (
+                "/cherry-pick status",
+                vec![Ok(Command::CherryPick(CherryPickCommand::Status))],
+            ),
+            (
+                "/cherry-pick",
+                vec![Err(CommandError::MissingBranch("/cherry-pick".to_string()))],
+            ),
+            (
+                "/cherry-pick\nrelease-1.2",
+                vec![Err(CommandError::MissingBranch("/cherry-pick".to_string()))],
+            ),
+            (
+                "/cherry-pick retry",
+                vec![Err(CommandError::MissingBranch("retry".to_string()))],
+            ),
+            (
+                "/cherry-pick release-1.2 release-1.3",
+                vec![Err(CommandError::UnexpectedArgument(
+                    "release-1.3".to_string(),
+                ))],
+            ),
+            (
+                "/cherry-pick status release-1.2",
+                vec![Err(CommandError::UnexpectedArgument(
+                    "release-1.2".to_string(),
+                ))],
+            ),
+            (
+                "/cherry-pick cancel --draft release-1.2",
+                vec![Err(CommandError::UnexpectedArgument("--draft".to_string()))],
+            ),
+            (
+                "/cherry-pick --force release-1.2",
+                vec![Err(CommandError::UnknownFlag("--force".to_string()))],
+            ),
+            (
+                "/cherry-pick --strategy=rebase release-1.2",
+                vec![Err(CommandError::InvalidFlagValue {
+                    flag: "strategy".to_string(),
+                    value: "rebase".to_string(),
+                })],
+            ),
+            (
+                "/cherry-pick release..1.2",
+                vec![Err(CommandError::InvalidBranch("release..1.2".to_string()))],
+            ),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(parse_commands(text), expected, "text: {}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_invite_commands() {
+        let cases = vec![
+            ("lbwnb", false),
+            ("/cherrypick-", false),
+            ("/cherry-pick", false),
+            ("/cherrypick-invite", true),
+            ("/cherry-pick-invite", true),
+            ("/cherrypick-invitexx", false),
+            ("/cherrypick-invite lbw", true),
+            ("/cherrypick-invite_lbw", false),
+            ("> /cherrypick-invite", false),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(
+                parse_commands(text).contains(&Ok(Command::Invite)),
+                expected,
+                "text: {}",
+                text
+            );
+        }
+    }
+
+    fn branch() -> impl proptest::strategy::Strategy<Value = String> {
+        "[a-zA-Z0-9][a-zA-Z0-9_/-]{0,15}(\\.[0-9]{1,3}){0,2}"
+            .prop_filter("valid branch", |b| is_valid_branch(b))
+            .prop_filter("not a subcommand", |b| !SUBCOMMANDS.contains(&b.as_str()))
+    }
+
+    proptest! {
+        #[test]
+        fn parses_any_valid_branch(branch in branch(), command in "/cherry-?pick", space in "[ \t]{1,3}") {
+            let expected = vec![pick(&branch)];
+            prop_assert_eq!(&parse_commands(format!("{}{}{}", command, space, branch)), &expected);
+            prop_assert_eq!(&parse_commands(format!("{} \"{}\"", command, branch)), &expected);
+        }
+
+        #[test]
+        fn ignores_commands_in_code_blocks(branch in branch(), fence in "```|~~~", info in "[a-z]{0,5}") {
+            let text = format!("{}{}\n/cherry-pick {}\n{}", fence, info, branch, fence);
+            prop_assert!(parse_commands(text).is_empty());
+        }
+
+        #[test]
+        fn ignores_commands_in_quotes(branch in branch(), indent in " {0,3}", depth in 1..4usize) {
+            let text = format!("{}{} /cherry-pick {}", indent, ">".repeat(depth), branch);
+            prop_assert!(parse_commands(text).is_empty());
+        }
+
+        #[test]
+        fn never_panics(text in "\\PC*") {
+            parse_commands(text);
+        }
+    }
+}
@@ -1,33 +1,50 @@
-use actions::{get_input, get_multiline_input};
+use std::path::PathBuf;
+
+use actions::{get_boolean_input, get_input, get_multiline_input, InputError};
 
 #[cfg(not(test))]
 pub use imp::*;
 #[cfg(test)]
 pub use tests::*;
 
+#[cfg(not(test))]
 mod imp {
+    use once_cell::sync::OnceCell;
+
     use super::Config;
 
-    pub fn config() -> &'static Config {
-        use lazy_static::lazy_static;
+    static CONFIG: OnceCell<Config> = OnceCell::new();
 
-        lazy_static! {
-            static ref CONFIG: Config = Config::from_actions();
-        }
-        &CONFIG
+    /// Sets the global config. Must be called before `config()`.
+    pub fn init_config(c: Config) {
+        CONFIG.set(c).expect("config is already initialised");
+    }
+
+    pub fn config() -> &'static Config {
+        CONFIG.get().expect("config is not initialised")
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Mutex;
+    use std::sync::{Mutex, MutexGuard};
 
     use lazy_static::lazy_static;
 
     use super::Config;
 
     lazy_static! {
-        static ref CONFIG: Mutex<Config> = Mutex::new(Config::from_actions());
+        static ref CONFIG: Mutex<Config> = Mutex::new(Config::from_actions().unwrap());
+        static ref LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    /// Serializes the tests replacing the config, which is shared by all of them.
+    pub fn lock_config() -> MutexGuard<'static, ()> {
+        LOCK.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn init_config(c: Config) {
+        replace_config(c)
     }
 
     pub fn config() -> Config {
@@ -49,26 +66,53 @@ pub struct Config {
     pub label_prefix: String,
     // Specifies the label prefix after picked.
     pub picked_label_prefix: String,
+    // Specifies the label prefix after a failed pick, which the sweep mode doesn't retry.
+    pub failed_label_prefix: String,
     // Specifies the labels that need to be excluded when copying the labels of the original PR.
     pub exclude_labels: Vec<String>,
     // Specifies whether to copy the issue numbers from the squashed commit message.
     pub copy_issue_numbers_from_squashed_commit: bool,
+    // Specifies the branches backports flow through, in order: each one is picked from the
+    // merged backport onto the previous requested branch, and waits until it is merged.
+    pub chain_backports: Vec<String>,
+    // Specifies how many days back the sweep mode looks for merged PRs with pending cherry-picks.
+    pub sweep_lookback_days: u32,
+    // Specifies whether the sweep mode only reports the pending cherry-picks.
+    pub dry_run: bool,
+    // Specifies the directory keeping a mirror of the repository across runs, instead of
+    // cloning it for every pick.
+    pub cache_dir: Option<PathBuf>,
+    // Specifies the depth of the clones, which are deepened as needed, for a shallow clone.
+    pub clone_depth: Option<u32>,
+    // Specifies the objects omitted from the clones, e.g. `blob:none`, for a partial clone.
+    pub clone_filter: Option<String>,
 }
 
 impl Config {
-    pub fn from_actions() -> Self {
-        Self {
-            allow_all: get_input("allow-all").unwrap_or(false),
-            create_issue_on_conflict: get_input("create-issue-on-conflict").unwrap_or(false),
-            label_prefix: get_input("label-prefix")
+    pub fn from_actions() -> Result<Self, InputError> {
+        Ok(Self {
+            allow_all: get_boolean_input("allow-all")?.unwrap_or(false),
+            create_issue_on_conflict: get_boolean_input("create-issue-on-conflict")?
+                .unwrap_or(false),
+            label_prefix: get_input("label-prefix")?
                 .unwrap_or_else(|| "needs-cherry-pick/".to_string()),
-            picked_label_prefix: get_input("picked-label-prefix")
+            picked_label_prefix: get_input("picked-label-prefix")?
                 .unwrap_or_else(|| "cherry-picked/".to_string()),
+            failed_label_prefix: get_input("failed-label-prefix")?
+                .unwrap_or_else(|| "cherry-pick-failed/".to_string()),
             exclude_labels: get_multiline_input("exclude-labels"),
-            copy_issue_numbers_from_squashed_commit: get_input(
+            copy_issue_numbers_from_squashed_commit: get_boolean_input(
                 "copy-issue-numbers-from-squashed-commit",
-            )
+            )?
             .unwrap_or_default(),
-        }
+            chain_backports: get_multiline_input("chain-backports"),
+            sweep_lookback_days: get_input("sweep-lookback-days")?.unwrap_or(7),
+            dry_run: get_boolean_input("dry-run")?.unwrap_or(false),
+            cache_dir: get_input::<String>("cache-dir")?
+                .filter(|x| !x.is_empty())
+                .map(PathBuf::from),
+            clone_depth: get_input("clone-depth")?,
+            clone_filter: get_input::<String>("clone-filter")?.filter(|x| !x.is_empty()),
+        })
     }
 }
@@ -0,0 +1,162 @@
+use octocrab::models::pulls::PullRequest;
+use serde::Deserialize;
+
+/// A `pull_request`, `pull_request_target` or `issues` event on a pull request.
+///
+/// The event is decoded from the raw payload, as octocrab only models the payloads of
+/// the events API, which `pull_request_target` and `issues` webhooks don't always match,
+/// and drops the fields they don't have, e.g. the `label` of `labeled` events.
+#[derive(Debug)]
+pub struct PullRequestEvent {
+    pub action: String,
+    pub number: u64,
+    // The label added or removed, for the `labeled` and `unlabeled` actions.
+    pub label: Option<String>,
+    // The pull request, if the payload has one octocrab can model.
+    pub pull_request: Option<PullRequest>,
+}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    action: String,
+    label: Option<RawLabel>,
+    pull_request: Option<serde_json::Value>,
+    issue: Option<RawIssue>,
+}
+
+#[derive(Deserialize)]
+struct RawLabel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RawIssue {
+    number: u64,
+    // Only present if the issue is a pull request.
+    pull_request: Option<serde_json::Value>,
+}
+
+impl PullRequestEvent {
+    /// Decodes the event from the raw `payload`, or returns None if it is not about a pull
+    /// request.
+    pub fn from_payload(payload: serde_json::Value) -> serde_json::Result<Option<Self>> {
+        let raw: RawEvent = serde_json::from_value(payload)?;
+        let (number, pull_request) = match (raw.pull_request, raw.issue) {
+            (Some(pr), _) => match pr.get("number").and_then(|x| x.as_u64()) {
+                Some(number) => (number, serde_json::from_value(pr).ok()),
+                None => return Ok(None),
+            },
+            (None, Some(issue)) if issue.pull_request.is_some() => (issue.number, None),
+            _ => return Ok(None),
+        };
+        Ok(Some(Self {
+            action: raw.action,
+            number,
+            label: raw.label.map(|label| label.name),
+            pull_request,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use octocrab::models::events::payload::EventPayload;
+    use serde_json::json;
+
+    use super::*;
+
+    fn from_json(value: serde_json::Value) -> Option<PullRequestEvent> {
+        PullRequestEvent::from_payload(value).unwrap()
+    }
+
+    #[test]
+    fn test_from_payload() {
+        // A `labeled` pull request webhook, trimmed to the fields octocrab requires.
+        let payload = json!({
+            "action": "labeled",
+            "number": 42,
+            "pull_request": {
+                "url": "https://api.github.com/repos/o/r/pulls/42",
+                "id": 1,
+                "number": 42,
+                "state": "closed",
+                "title": "Fix the frobnicator",
+                "labels": [{
+                    "id": 2,
+                    "node_id": "LA_2",
+                    "url": "https://api.github.com/repos/o/r/labels/needs-cherry-pick/release-1.0",
+                    "name": "needs-cherry-pick/release-1.0",
+                    "color": "ededed",
+                    "default": false,
+                }],
+                "merged_at": "2022-12-01T10:00:00Z",
+                "merge_commit_sha": "6dcb09b5b57875f334f61aebed695e2e4193db5e",
+                "head": { "ref": "fix", "sha": "e5bd3914e2e596debea16f433f57875b5b90bcd6" },
+                "base": { "ref": "main", "sha": "553c2077f0edc3d5dc5d17262f6aa498e69d6f8e" },
+            },
+            "label": { "name": "needs-cherry-pick/release-1.0" },
+            "repository": { "full_name": "o/r" },
+        });
+        // octocrab models it, dropping the label.
+        let decoded: EventPayload = serde_json::from_value(payload.clone()).unwrap();
+        assert!(
+            !matches!(decoded, EventPayload::UnknownEvent(_)),
+            "{:?}",
+            decoded
+        );
+        assert!(serde_json::to_value(&decoded)
+            .unwrap()
+            .get("label")
+            .is_none());
+
+        let event = from_json(payload).unwrap();
+        assert_eq!(event.action, "labeled");
+        assert_eq!(event.number, 42);
+        assert_eq!(
+            event.label.as_deref(),
+            Some("needs-cherry-pick/release-1.0")
+        );
+        assert_eq!(
+            event
+                .pull_request
+                .and_then(|pr| pr.merge_commit_sha)
+                .as_deref(),
+            Some("6dcb09b5b57875f334f61aebed695e2e4193db5e")
+        );
+
+        let event = from_json(json!({
+            "action": "labeled",
+            "label": { "name": "needs-cherry-pick/release-1.0" },
+            "issue": {
+                "number": 42,
+                "pull_request": { "url": "https://api.github.com/repos/o/r/pulls/42" },
+            },
+        }))
+        .unwrap();
+        assert_eq!(event.action, "labeled");
+        assert_eq!(event.number, 42);
+        assert_eq!(
+            event.label.as_deref(),
+            Some("needs-cherry-pick/release-1.0")
+        );
+        assert!(event.pull_request.is_none());
+
+        // An incomplete pull request is fetched later rather than rejected.
+        let event = from_json(json!({
+            "action": "closed",
+            "pull_request": { "number": 7 },
+        }))
+        .unwrap();
+        assert_eq!(event.number, 7);
+        assert!(event.label.is_none());
+        assert!(event.pull_request.is_none());
+
+        // Labels on plain issues are ignored.
+        assert!(from_json(json!({
+            "action": "labeled",
+            "label": { "name": "needs-cherry-pick/release-1.0" },
+            "issue": { "number": 1 },
+        }))
+        .is_none());
+    }
+}
@@ -1,28 +1,80 @@
-use std::{env::temp_dir, path::PathBuf, time::Duration};
+use std::time::Duration;
 
 use anyhow::{anyhow, Context};
-use git::{Git, GitBuilder};
+use chrono::{DateTime, Utc};
+use git::{CloneOptions, GitBuilder, Mirror, Workspace};
 use logit::Logit;
-use octocrab::models::pulls::PullRequest;
+use octocrab::models::{
+    pulls::PullRequest,
+    reactions::{Reaction, ReactionContent},
+    CommentId, ReactionId, Repository,
+};
 use random_color::RandomColor;
-use tokio::{fs, time};
+use serde::{de::IgnoredAny, Deserialize};
+use serde_json::json;
+use tokio::time;
 
 use crate::config::config;
 
-use self::{api::list_repos_for_user, page::list_all};
+pub use self::{
+    auth::{authenticate, init_auth, App, Auth},
+    client::usage,
+    endpoint::{endpoint, init_endpoint, Endpoint},
+    graphql::{find_backports, pull_info, pull_infos, Backport, BackportState},
+    patch::download_patch,
+};
 
-mod api;
-mod page;
+use self::{
+    api::{create_issue_comment_reaction, delete_issue_comment_reaction},
+    auth::is_app,
+    client::Method,
+};
 
-pub async fn client_for(owner: impl Into<String>, repo: impl Into<String>) -> Git {
-    let owner = owner.into();
-    let repo = repo.into();
+mod api;
+mod auth;
+mod client;
+mod endpoint;
+mod graphql;
+mod patch;
+
+/// Creates a workspace checked out at `target_branch` of owner/repo: a worktree of its
+/// mirror in the cache directory if configured, else a clone. Either way the branch comes
+/// from the upstream repo, the fork is only pushed to.
+pub async fn workspace_for(
+    owner: &str,
+    repo: &str,
+    target_branch: &str,
+) -> anyhow::Result<Workspace> {
+    let builder = auth::git_env(owner, repo)
+        .await?
+        .into_iter()
+        .fold(GitBuilder::new(owner, repo), |builder, (key, value)| {
+            builder.env(key, value)
+        });
+    if let Some(cache_dir) = &config().cache_dir {
+        let mirror = Mirror::new(cache_dir.join(owner).join(format!("{}.git", repo)));
+        return mirror
+            .workspace(builder, endpoint().clone_url(owner, repo), target_branch)
+            .await;
+    }
 
-    let mut git = GitBuilder::new(&owner, &repo).build();
-    git.clone(format!("https://github.com/{}/{}.git", owner, repo))
+    let mut workspace = Workspace::new(builder).context("create workspace")?;
+    // Only the target branch is needed, the changes are fetched from the upstream repo.
+    let options = CloneOptions {
+        depth: config().clone_depth,
+        filter: config().clone_filter.clone(),
+        branch: Some(target_branch.to_string()),
+        no_tags: true,
+    };
+    workspace
+        .clone(endpoint().clone_url(owner, repo), &options)
+        .await
+        .context("clone repo")?;
+    workspace
+        .checkout(format!("origin/{}", target_branch))
         .await
-        .logit("clone repo");
-    git
+        .context("checkout to target branch")?;
+    Ok(workspace)
 }
 
 // `ensureFork` checks to see that there is a fork of org/repo in the forkedUsers repositories.
@@ -41,14 +93,16 @@ pub async fn ensure_fork(
         return Ok(repo);
     }
 
-    let forked = octocrab::instance()
-        .repos(&owner, &repo)
-        .create_fork()
-        .send()
-        .await
-        .context("create fork")?;
+    let forked: Repository = client::post(
+        format!("repos/{}/{}/forks", owner, repo),
+        &serde_json::json!({}),
+    )
+    .await
+    .context("create fork")?;
 
-    wait_for_repo(owner, repo).await.context("wait for repo")?;
+    wait_for_repo(&forking_user, &forked.name)
+        .await
+        .context("wait for repo")?;
 
     Ok(forked.name)
 }
@@ -63,28 +117,17 @@ pub async fn is_forked(
     let owner = owner.into();
     let repo = repo.into();
 
-    let fork = format!("{}/{}", forking_user, repo);
-
-    let repos = list_all(list_repos_for_user(&forking_user)).await.unwrap();
-    let forked_repo = repos
-        .into_iter()
-        .find(|repo| repo.fork == Some(true) && repo.full_name.as_ref() == Some(&fork));
-
-    let forked_repo = match forked_repo {
-        Some(x) => x,
-        None => return false,
-    };
-
-    let parent_full_name = octocrab::instance()
-        .repos(forking_user, forked_repo.name)
-        .get()
-        .await
-        .map(|r| r.parent.and_then(|p| p.full_name))
-        .unwrap();
-
-    match parent_full_name {
-        Some(n) => n == format!("{}/{}", owner, repo),
-        None => false,
+    match client::get::<Repository>(format!("repos/{}/{}", forking_user, repo)).await {
+        Ok(fork) => {
+            fork.fork == Some(true)
+                && fork.parent.and_then(|p| p.full_name) == Some(format!("{}/{}", owner, repo))
+        }
+        Err(err) => {
+            if !err.is_not_found() {
+                tracing::warn!("get {}/{}: {}", forking_user, repo, err);
+            }
+            false
+        }
     }
 }
 
@@ -105,9 +148,7 @@ pub async fn wait_for_repo(
     if let Err(_) = time::timeout(Duration::from_secs(6 * 60), async move {
         loop {
             interval.tick().await;
-            match octocrab::instance()
-                .repos(owner.clone(), repo.clone())
-                .get()
+            match client::get::<Repository>(format!("repos/{}/{}", owner, repo))
                 .await
                 .logit_warn("Error getting bot repository.")
             {
@@ -128,6 +169,150 @@ pub async fn wait_for_repo(
     Ok(())
 }
 
+/// Returns the owner of the repository backports are pushed to: `owner` itself when
+/// authenticated as a GitHub App, as apps cannot own forks, or else the authenticated user.
+pub async fn forking_user(owner: &str) -> anyhow::Result<String> {
+    if is_app() {
+        return Ok(owner.to_string());
+    }
+    current_user().await
+}
+
+/// Returns the login of the authenticated user.
+pub async fn current_user() -> anyhow::Result<String> {
+    #[derive(Deserialize)]
+    struct User {
+        login: String,
+    }
+
+    client::get("user")
+        .await
+        .map(|user: User| user.login)
+        .context("get current user")
+}
+
+/// Returns true if `user` is a collaborator of owner/repo.
+pub async fn is_collaborator(
+    owner: impl Into<String>,
+    repo: impl Into<String>,
+    user: impl Into<String>,
+) -> anyhow::Result<bool> {
+    let user: String = user.into();
+    let route = format!(
+        "repos/{}/{}/collaborators/{}",
+        owner.into(),
+        repo.into(),
+        encode(&user)
+    );
+    // No Content if the user is a collaborator, else Not Found.
+    match client::send(Method::Get, route, None, None::<&()>).await {
+        Ok(_) => Ok(true),
+        Err(err) if err.is_not_found() => Ok(false),
+        Err(err) => Err(err).context("check collaborator"),
+    }
+}
+
+/// Name of the branch the backport of `pull_number` onto `target_branch` is pushed to.
+pub fn backport_branch(pull_number: u64, target_branch: impl AsRef<str>) -> String {
+    format!("cherry-pick-{}-to-{}", pull_number, target_branch.as_ref())
+}
+
+/// Parses the pull request number and the target branch out of the name of a backport branch.
+pub fn parse_backport_branch(branch: &str) -> Option<(u64, String)> {
+    let (number, target_branch) = branch.strip_prefix("cherry-pick-")?.split_once("-to-")?;
+    if target_branch.is_empty() {
+        return None;
+    }
+    Some((number.parse().ok()?, target_branch.to_string()))
+}
+
+/// Returns the backport of `pull_number` onto `target_branch` opened from the fork of
+/// `forking_user`, whether it is still open or not.
+pub async fn find_backport(
+    forking_user: impl Into<String>,
+    owner: impl Into<String>,
+    repo: impl Into<String>,
+    pull_number: u64,
+    target_branch: impl Into<String>,
+) -> anyhow::Result<Option<PullRequest>> {
+    let target_branch = target_branch.into();
+    let head = format!(
+        "{}:{}",
+        forking_user.into(),
+        backport_branch(pull_number, &target_branch)
+    );
+
+    let items: Vec<PullRequest> = client::get(format!(
+        "repos/{}/{}/pulls?state=all&head={}&base={}",
+        owner.into(),
+        repo.into(),
+        encode(&head),
+        encode(&target_branch)
+    ))
+    .await
+    .context("list backports")?;
+    Ok(items.into_iter().next())
+}
+
+pub async fn get_pull(
+    owner: impl AsRef<str>,
+    repo: impl AsRef<str>,
+    pull_number: u64,
+) -> anyhow::Result<PullRequest> {
+    client::get(format!(
+        "repos/{}/{}/pulls/{}",
+        owner.as_ref(),
+        repo.as_ref(),
+        pull_number
+    ))
+    .await
+    .context("get pull request")
+}
+
+/// Lists the PRs merged since `since`.
+pub async fn list_merged_since(
+    owner: impl AsRef<str>,
+    repo: impl AsRef<str>,
+    since: DateTime<Utc>,
+) -> anyhow::Result<Vec<PullRequest>> {
+    let mut prs = Vec::new();
+    for page in 1u32.. {
+        let items: Vec<PullRequest> = client::get(format!(
+            "repos/{}/{}/pulls?state=closed&sort=updated&direction=desc&per_page=100&page={}",
+            owner.as_ref(),
+            repo.as_ref(),
+            page
+        ))
+        .await
+        .context("list closed pull requests")?;
+
+        // PRs merged since `since` were updated since then too, the rest can be skipped.
+        let mut done = items.len() < 100;
+        for pr in items {
+            if !matches!(pr.updated_at, Some(updated_at) if updated_at >= since) {
+                done = true;
+                break;
+            }
+            if matches!(pr.merged_at, Some(merged_at) if merged_at >= since) {
+                prs.push(pr);
+            }
+        }
+        if done {
+            break;
+        }
+    }
+    Ok(prs)
+}
+
+/// Returns whether a previous pick of `pr` to `target_branch` failed.
+pub fn is_failed(pr: &PullRequest, target_branch: &str) -> bool {
+    let failed_label_prefix = &config().failed_label_prefix;
+    pr.labels
+        .iter()
+        .flatten()
+        .any(|label| label.name.strip_prefix(failed_label_prefix) == Some(target_branch))
+}
+
 pub fn is_picked(pr: &PullRequest, target_branch: impl Into<String>) -> bool {
     let labels = match &pr.labels {
         Some(x) => x,
@@ -151,57 +336,196 @@ pub async fn ensure_label(
     let repo = repo.into();
     let label = label.into();
 
-    octocrab::instance()
-        .issues(owner, repo)
-        .create_label(&label, &RandomColor::new().to_hex()[1..], &label)
-        .await
-        .context("create label")?;
-    Ok(())
+    let route = format!("repos/{}/{}/labels", owner, repo);
+    match client::get::<IgnoredAny>(format!("{}/{}", route, encode(&label))).await {
+        Ok(_) => return Ok(()),
+        Err(err) if err.is_not_found() => {}
+        Err(err) => return Err(err).context("get label"),
+    }
+    let body = json!({
+        "name": label,
+        "color": &RandomColor::new().to_hex()[1..],
+        "description": label,
+    });
+    match client::post::<IgnoredAny>(route, &body).await {
+        Ok(_) => Ok(()),
+        // Created meanwhile by another run.
+        Err(err) if err.status() == Some(422) => Ok(()),
+        Err(err) => Err(err).context("create label"),
+    }
 }
 
-pub async fn download_patch(
-    owner: impl Into<String>,
-    repo: impl Into<String>,
-    pull_number: u64,
-    target_branch: impl Into<String>,
-) -> anyhow::Result<PathBuf> {
-    let owner = owner.into();
-    let repo = repo.into();
+/// Adds `labels` to the issue or pull request `number`.
+pub async fn add_labels(
+    owner: impl AsRef<str>,
+    repo: impl AsRef<str>,
+    number: u64,
+    labels: &[String],
+) -> anyhow::Result<()> {
+    client::post::<IgnoredAny>(
+        format!(
+            "repos/{}/{}/issues/{}/labels",
+            owner.as_ref(),
+            repo.as_ref(),
+            number
+        ),
+        &json!({ "labels": labels }),
+    )
+    .await
+    .context("add labels")?;
+    Ok(())
+}
 
-    let filename = format!(
-        "{}-{}-{}-{}",
-        owner,
-        repo,
-        pull_number,
-        normalize(target_branch.into())
-    );
+/// Removes `label` from the issue or pull request `number`.
+pub async fn remove_label(
+    owner: impl AsRef<str>,
+    repo: impl AsRef<str>,
+    number: u64,
+    label: impl AsRef<str>,
+) -> anyhow::Result<()> {
+    client::delete(format!(
+        "repos/{}/{}/issues/{}/labels/{}",
+        owner.as_ref(),
+        repo.as_ref(),
+        number,
+        encode(label.as_ref())
+    ))
+    .await
+    .context("remove label")
+}
 
-    let pr = octocrab::instance()
-        .pulls(owner, repo)
-        .get(pull_number)
-        .await
-        .context("get pull request")?;
+/// Opens an issue titled `title`.
+pub async fn create_issue(
+    owner: impl AsRef<str>,
+    repo: impl AsRef<str>,
+    title: impl AsRef<str>,
+    body: impl AsRef<str>,
+) -> anyhow::Result<()> {
+    client::post::<IgnoredAny>(
+        format!("repos/{}/{}/issues", owner.as_ref(), repo.as_ref()),
+        &json!({ "title": title.as_ref(), "body": body.as_ref() }),
+    )
+    .await
+    .context("create issue")?;
+    Ok(())
+}
 
-    let resp = reqwest::get(
-        pr.patch_url
-            .with_context(|| format!("pull request patch_url is None. {}", filename))?,
+/// Opens a pull request of `head`, either a branch or `user:branch`, into `base`.
+pub async fn create_pull(
+    owner: impl AsRef<str>,
+    repo: impl AsRef<str>,
+    title: impl AsRef<str>,
+    head: impl AsRef<str>,
+    base: impl AsRef<str>,
+    body: impl AsRef<str>,
+    draft: bool,
+) -> anyhow::Result<PullRequest> {
+    client::post(
+        format!("repos/{}/{}/pulls", owner.as_ref(), repo.as_ref()),
+        &json!({
+            "title": title.as_ref(),
+            "head": head.as_ref(),
+            "base": base.as_ref(),
+            "body": body.as_ref(),
+            "draft": draft,
+        }),
     )
     .await
-    .unwrap();
-
-    let p = temp_dir().join(&filename);
-    fs::write(
-        &p,
-        resp.bytes()
-            .await
-            .with_context(|| format!("get the fetch response body {}", filename))?,
+    .context("create pull request")
+}
+
+/// Percent-encodes `s` for a path segment or a query value, e.g. a label with a `/`.
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub async fn create_comment(
+    owner: impl Into<String>,
+    repo: impl Into<String>,
+    number: u64,
+    body: impl AsRef<str>,
+) -> anyhow::Result<()> {
+    client::post::<IgnoredAny>(
+        format!(
+            "repos/{}/{}/issues/{}/comments",
+            owner.into(),
+            repo.into(),
+            number
+        ),
+        &json!({ "body": body.as_ref() }),
     )
     .await
-    .with_context(|| format!("write patch file {}", filename))?;
+    .context("create comment")?;
+    Ok(())
+}
 
-    Ok(p)
+/// Reacts to the issue comment `comment_id` with `content`.
+pub async fn react(
+    owner: impl Into<String>,
+    repo: impl Into<String>,
+    comment_id: CommentId,
+    content: ReactionContent,
+) -> anyhow::Result<Reaction> {
+    create_issue_comment_reaction(owner, repo, comment_id, content)
+        .await
+        .context("create reaction")
+}
+
+/// Removes the reaction `reaction_id` from the issue comment `comment_id`.
+pub async fn unreact(
+    owner: impl Into<String>,
+    repo: impl Into<String>,
+    comment_id: CommentId,
+    reaction_id: ReactionId,
+) -> anyhow::Result<()> {
+    delete_issue_comment_reaction(owner, repo, comment_id, reaction_id)
+        .await
+        .context("delete reaction")
 }
 
-pub fn normalize(input: impl AsRef<str>) -> String {
-    input.as_ref().replace('/', "-")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let cases = vec![
+            ("release-1.0", "release-1.0"),
+            (
+                "needs-cherry-pick/release-1.0",
+                "needs-cherry-pick%2Frelease-1.0",
+            ),
+            ("fork:cherry-pick-1-to-v1", "fork%3Acherry-pick-1-to-v1"),
+            ("a b&c+d#é", "a%20b%26c%2Bd%23%C3%A9"),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(encode(s), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_backport_branch() {
+        let cases = vec![
+            ("cherry-pick-12-to-release-1.0", Some((12, "release-1.0"))),
+            ("cherry-pick-12-to-back-to-v1", Some((12, "back-to-v1"))),
+            ("cherry-pick-12-to-", None),
+            ("cherry-pick-x-to-main", None),
+            ("feature-to-main", None),
+        ];
+        for (branch, expected) in cases {
+            assert_eq!(
+                parse_backport_branch(branch),
+                expected.map(|(number, target)| (number, target.to_string())),
+                "{}",
+                branch
+            );
+        }
+    }
 }
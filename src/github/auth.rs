@@ -0,0 +1,254 @@
+use std::{collections::HashMap, env, sync::Mutex as SyncMutex};
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::endpoint::endpoint;
+
+/// How the cherry-picker authenticates to GitHub.
+pub enum Auth {
+    /// A personal access token, or the `GITHUB_TOKEN` of a workflow.
+    Token(String),
+    /// A GitHub App, acting through the installation tokens of each repository.
+    App(App),
+}
+
+pub struct App {
+    id: u64,
+    key: EncodingKey,
+    // Installation tokens by repository, as `owner/repo`.
+    tokens: Mutex<HashMap<String, InstallationToken>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Claims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+impl Claims {
+    /// Backdated against clock drift, and valid for less than the maximum 10 minutes.
+    fn new(app_id: u64, now: i64) -> Self {
+        Self {
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: app_id.to_string(),
+        }
+    }
+}
+
+impl InstallationToken {
+    /// Returns true if the token is still valid for more than 10 minutes at `now`.
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at - now > Duration::minutes(10)
+    }
+}
+
+#[derive(Deserialize)]
+struct Installation {
+    id: u64,
+}
+
+impl App {
+    pub fn new(id: u64, private_key: impl AsRef<[u8]>) -> anyhow::Result<Self> {
+        Ok(Self {
+            id,
+            key: EncodingKey::from_rsa_pem(private_key.as_ref())
+                .context("parse app private key")?,
+            tokens: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Signs a JWT authenticating as the app itself.
+    fn jwt(&self) -> anyhow::Result<String> {
+        let claims = Claims::new(self.id, Utc::now().timestamp());
+        jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.key)
+            .context("sign app jwt")
+    }
+
+    /// Returns a token of the installation on `owner/repo`, which is valid for at least
+    /// 10 more minutes.
+    async fn installation_token(&self, owner: &str, repo: &str) -> anyhow::Result<String> {
+        let full_name = format!("{}/{}", owner, repo);
+        let mut tokens = self.tokens.lock().await;
+        if let Some(token) = tokens.get(&full_name) {
+            if token.is_fresh(Utc::now()) {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let app = endpoint()
+            .client_builder()?
+            .personal_token(self.jwt()?)
+            .build()
+            .context("build app client")?;
+        let installation: Installation = app
+            .get(format!("repos/{}/installation", full_name), None::<&()>)
+            .await
+            .with_context(|| format!("get app installation on {}", full_name))?;
+        let token: InstallationToken = app
+            .post(
+                format!("app/installations/{}/access_tokens", installation.id),
+                None::<&()>,
+            )
+            .await
+            .context("create installation token")?;
+        tracing::info!(
+            installation_id = installation.id,
+            "created installation token expiring at {}",
+            token.expires_at
+        );
+        tokens.insert(full_name, token.clone());
+        Ok(token.token)
+    }
+}
+
+static AUTH: OnceCell<Auth> = OnceCell::new();
+// The token `octocrab::instance()` was last initialised with.
+static CLIENT_TOKEN: SyncMutex<Option<String>> = SyncMutex::new(None);
+
+// The variable git reads the current token from.
+const TOKEN_VAR: &str = "CHERRYPICKER_GIT_TOKEN";
+
+/// Sets how to authenticate. Must be called after `init_endpoint()`, and before
+/// `authenticate()`.
+pub fn init_auth(auth: Auth) {
+    if AUTH.set(auth).is_err() {
+        panic!("auth is already initialised");
+    }
+}
+
+/// Returns the token for `owner/repo`, refreshing installation tokens when they expire
+/// within 10 minutes.
+async fn token(owner: &str, repo: &str) -> anyhow::Result<String> {
+    match AUTH.get().context("auth is not initialised")? {
+        Auth::Token(token) => Ok(token.clone()),
+        Auth::App(app) => app.installation_token(owner, repo).await,
+    }
+}
+
+/// Authenticates `octocrab::instance()` for `owner/repo`. Installation tokens are
+/// refreshed when they expire within 10 minutes, so call this before each unit of work.
+pub async fn authenticate(owner: &str, repo: &str) -> anyhow::Result<()> {
+    let token = token(owner, repo).await?;
+    let mut client_token = CLIENT_TOKEN.lock().unwrap();
+    if client_token.as_deref() == Some(token.as_str()) {
+        return Ok(());
+    }
+
+    // Workflow commands are only understood by the runner.
+    if env::var("GITHUB_ACTIONS").as_deref() == Ok("true") {
+        actions::add_mask(&token);
+    }
+    octocrab::initialise(endpoint().client_builder()?.personal_token(token.clone()))
+        .context("initialise octocrab")?;
+    *client_token = Some(token);
+    Ok(())
+}
+
+/// Returns the environment variables authenticating the git commands on `owner/repo`.
+pub async fn git_env(owner: &str, repo: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let token = token(owner, repo).await?;
+    let count = env::var("GIT_CONFIG_COUNT")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(0);
+    Ok(credential_env(&endpoint().server_url, count, token))
+}
+
+/// git reads `token` through a credential helper for `server_url`, so that it never shows
+/// up in command lines or remote urls. The helper is configured through the environment,
+/// after the `count` entries of configuration already there.
+fn credential_env(server_url: &str, count: usize, token: String) -> Vec<(String, String)> {
+    vec![
+        (
+            format!("GIT_CONFIG_KEY_{}", count),
+            format!("credential.{}.helper", server_url),
+        ),
+        (
+            format!("GIT_CONFIG_VALUE_{}", count),
+            format!(
+                "!f() {{ test \"$1\" = get && echo username=x-access-token && echo \"password=${}\"; }}; f",
+                TOKEN_VAR
+            ),
+        ),
+        ("GIT_CONFIG_COUNT".to_string(), (count + 1).to_string()),
+        (TOKEN_VAR.to_string(), token),
+    ]
+}
+
+/// Returns true if authenticated as a GitHub App, which cannot own forks.
+pub fn is_app() -> bool {
+    matches!(AUTH.get(), Some(Auth::App(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_claims() {
+        assert_eq!(
+            Claims::new(42, 1_700_000_000),
+            Claims {
+                iat: 1_700_000_000 - 60,
+                exp: 1_700_000_000 + 540,
+                iss: "42".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_installation_token_is_fresh() {
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let cases = vec![
+            (Duration::hours(1), true),
+            (Duration::minutes(11), true),
+            (Duration::minutes(10), false),
+            (Duration::minutes(1), false),
+            (Duration::minutes(-5), false),
+        ];
+        for (valid_for, expected) in cases {
+            let token = InstallationToken {
+                token: "ghs_x".to_string(),
+                expires_at: now + valid_for,
+            };
+            assert_eq!(token.is_fresh(now), expected, "valid for {}", valid_for);
+        }
+    }
+
+    #[test]
+    fn test_credential_env() {
+        let env = credential_env("https://github.com", 2, "ghs_x".to_string());
+        assert_eq!(
+            env[..1],
+            [(
+                "GIT_CONFIG_KEY_2".to_string(),
+                "credential.https://github.com.helper".to_string()
+            )]
+        );
+        assert!(env[1].1.contains("$CHERRYPICKER_GIT_TOKEN"), "{:?}", env[1]);
+        assert!(!env[1].1.contains("ghs_x"), "{:?}", env[1]);
+        assert_eq!(
+            env[2..],
+            [
+                ("GIT_CONFIG_COUNT".to_string(), "3".to_string()),
+                ("CHERRYPICKER_GIT_TOKEN".to_string(), "ghs_x".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT};
+use serde::{de::DeserializeOwned, Serialize};
+
+// Attempts of a request, including the first one.
+const MAX_ATTEMPTS: u32 = 5;
+// Waiting for the primary rate limit to reset longer than this fails the request instead.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Delete,
+}
+
+impl Method {
+    /// Returns true if sending the request twice has the same effect as sending it once,
+    /// so that it can be retried after server errors.
+    fn is_idempotent(self) -> bool {
+        !matches!(self, Method::Post)
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Delete => "DELETE",
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiError {
+    #[error("{method} {route} failed with status {status}: {message}")]
+    Status {
+        method: Method,
+        route: String,
+        status: u16,
+        message: String,
+    },
+    #[error("{method} {route} failed: {message}")]
+    Request {
+        method: Method,
+        route: String,
+        message: String,
+    },
+    #[error("decode response of {method} {route}")]
+    Decode {
+        method: Method,
+        route: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl ApiError {
+    pub fn is_not_found(&self) -> bool {
+        self.status() == Some(404)
+    }
+
+    /// Returns the status of the response, if any.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ApiError::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+/// The rate limit headers of a response.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct RateLimit {
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    // When the primary rate limit resets, in seconds since the epoch.
+    reset: Option<u64>,
+    retry_after: Option<u64>,
+}
+
+impl RateLimit {
+    fn from_headers<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Self {
+        let number = |name| header(name).and_then(|x| x.trim().parse().ok());
+        Self {
+            limit: number("x-ratelimit-limit"),
+            remaining: number("x-ratelimit-remaining"),
+            reset: number("x-ratelimit-reset"),
+            retry_after: number("retry-after"),
+        }
+    }
+}
+
+/// Returns how long to wait before retrying a request that failed with `status`, or
+/// None if it should not be retried.
+///
+/// Follows the GitHub documentation on rate limits: wait for `Retry-After` if present,
+/// else until the primary rate limit resets if it is exhausted, else at least a minute
+/// on secondary rate limits. Server errors are retried with exponential backoff.
+fn retry_delay(
+    method: Method,
+    status: u16,
+    rate_limit: &RateLimit,
+    message: &str,
+    attempt: u32,
+    now: u64,
+) -> Option<Duration> {
+    if attempt + 1 >= MAX_ATTEMPTS {
+        return None;
+    }
+    match status {
+        403 | 429 => {
+            if let Some(retry_after) = rate_limit.retry_after {
+                return Some(Duration::from_secs(retry_after));
+            }
+            if rate_limit.remaining == Some(0) {
+                let wait = Duration::from_secs(rate_limit.reset?.saturating_sub(now) + 1);
+                return (wait <= MAX_RATE_LIMIT_WAIT).then_some(wait);
+            }
+            if message.to_lowercase().contains("secondary rate limit") {
+                return Some(Duration::from_secs(60) * 2u32.pow(attempt));
+            }
+            None
+        }
+        500..=599 if method.is_idempotent() => Some(backoff(attempt)),
+        _ => None,
+    }
+}
+
+/// Returns an exponential backoff of one second and more, with jitter.
+fn backoff(attempt: u32) -> Duration {
+    let base = Duration::from_secs(1) * 2u32.pow(attempt);
+    base + base.mul_f64(rand::thread_rng().gen_range(0.0..1.0))
+}
+
+/// The API usage of this run, through the retrying client.
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub requests: u64,
+    pub retries: u64,
+    pub waited: Duration,
+    // The primary rate limit as of the last response.
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+}
+
+static USAGE: Lazy<Mutex<Usage>> = Lazy::new(|| Mutex::new(Usage::default()));
+
+/// Returns the API usage so far.
+pub fn usage() -> Usage {
+    USAGE.lock().unwrap().clone()
+}
+
+/// Sends a request to `route` of the API through `octocrab::instance()`, waiting out rate
+/// limits and retrying idempotent requests on server errors. Returns the response body as
+/// is, which may not be UTF-8.
+///
+/// `accept` overrides the media type of GET requests, e.g. to get a pull request as a patch.
+pub async fn send(
+    method: Method,
+    route: impl AsRef<str>,
+    accept: Option<&str>,
+    body: Option<&(impl Serialize + ?Sized)>,
+) -> Result<Vec<u8>, ApiError> {
+    let route = route.as_ref();
+    let request_error = |message: String| ApiError::Request {
+        method,
+        route: route.to_string(),
+        message,
+    };
+
+    let octocrab = octocrab::instance();
+    let url = octocrab
+        .absolute_url(route)
+        .map_err(|err| request_error(err.to_string()))?
+        .to_string();
+    let mut headers = HeaderMap::new();
+    if let Some(accept) = accept {
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_str(accept).map_err(|err| request_error(err.to_string()))?,
+        );
+    }
+    for attempt in 0.. {
+        let resp = match method {
+            Method::Get => {
+                octocrab
+                    ._get_with_headers(&url, None::<&()>, Some(headers.clone()))
+                    .await
+            }
+            Method::Post => octocrab._post(&url, body).await,
+            Method::Delete => octocrab._delete(&url, body).await,
+        };
+        USAGE.lock().unwrap().requests += 1;
+
+        let resp = match resp {
+            Ok(resp) => resp,
+            Err(err) if method.is_idempotent() && attempt + 1 < MAX_ATTEMPTS => {
+                let wait = backoff(attempt);
+                tracing::warn!(
+                    "{} {} failed, retrying in {:?}: {}",
+                    method,
+                    route,
+                    wait,
+                    err
+                );
+                wait_before_retry(wait).await;
+                continue;
+            }
+            Err(err) => return Err(request_error(err.to_string())),
+        };
+
+        let status = resp.status().as_u16();
+        let rate_limit = RateLimit::from_headers(|name| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        });
+        {
+            let mut usage = USAGE.lock().unwrap();
+            usage.limit = rate_limit.limit.or(usage.limit);
+            usage.remaining = rate_limit.remaining.or(usage.remaining);
+        }
+        let body = resp
+            .bytes()
+            .await
+            .map_err(|err| request_error(err.to_string()))?;
+        if (200..300).contains(&status) {
+            return Ok(body.to_vec());
+        }
+
+        let message = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|value| value.get("message")?.as_str().map(str::to_string))
+            .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_secs());
+        match retry_delay(method, status, &rate_limit, &message, attempt, now) {
+            Some(wait) => {
+                tracing::warn!(
+                    "{} {} failed with status {}, retrying in {:?}: {}",
+                    method,
+                    route,
+                    status,
+                    wait,
+                    message
+                );
+                wait_before_retry(wait).await;
+            }
+            None => {
+                return Err(ApiError::Status {
+                    method,
+                    route: route.to_string(),
+                    status,
+                    message,
+                })
+            }
+        }
+    }
+    unreachable!("the last attempt returns")
+}
+
+async fn wait_before_retry(wait: Duration) {
+    {
+        let mut usage = USAGE.lock().unwrap();
+        usage.retries += 1;
+        usage.waited += wait;
+    }
+    tokio::time::sleep(wait).await;
+}
+
+/// Sends a GET request to `route`, decoding the response.
+pub async fn get<T: DeserializeOwned>(route: impl AsRef<str>) -> Result<T, ApiError> {
+    let route = route.as_ref();
+    let body = send(Method::Get, route, None, None::<&()>).await?;
+    serde_json::from_slice(&body).map_err(|source| ApiError::Decode {
+        method: Method::Get,
+        route: route.to_string(),
+        source,
+    })
+}
+
+/// Sends a GET request to `route` accepting the media type `accept`, returning the
+/// response body as is.
+pub async fn get_raw(route: impl AsRef<str>, accept: &str) -> Result<Vec<u8>, ApiError> {
+    send(Method::Get, route, Some(accept), None::<&()>).await
+}
+
+/// Sends a POST request with `body` to `route`, decoding the response.
+pub async fn post<T: DeserializeOwned>(
+    route: impl AsRef<str>,
+    body: &(impl Serialize + ?Sized),
+) -> Result<T, ApiError> {
+    let route = route.as_ref();
+    let resp = send(Method::Post, route, None, Some(body)).await?;
+    serde_json::from_slice(&resp).map_err(|source| ApiError::Decode {
+        method: Method::Post,
+        route: route.to_string(),
+        source,
+    })
+}
+
+/// Sends a DELETE request to `route`.
+pub async fn delete(route: impl AsRef<str>) -> Result<(), ApiError> {
+    send(Method::Delete, route, None, None::<&()>)
+        .await
+        .map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn rate_limit(headers: &[(&str, &str)]) -> RateLimit {
+        let headers = headers.iter().copied().collect::<HashMap<_, _>>();
+        RateLimit::from_headers(|name| headers.get(name).copied())
+    }
+
+    #[test]
+    fn test_rate_limit_from_headers() {
+        assert_eq!(
+            rate_limit(&[
+                ("x-ratelimit-limit", "5000"),
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", "1700000000"),
+                ("retry-after", " 30"),
+            ]),
+            RateLimit {
+                limit: Some(5000),
+                remaining: Some(0),
+                reset: Some(1700000000),
+                retry_after: Some(30),
+            }
+        );
+        assert_eq!(
+            rate_limit(&[("x-ratelimit-remaining", "lots")]),
+            RateLimit::default()
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let now = 1_700_000_000;
+        let secs = |x| Some(Duration::from_secs(x));
+        let cases = vec![
+            // Secondary rate limit with Retry-After.
+            (
+                Method::Post,
+                403,
+                rate_limit(&[("retry-after", "30")]),
+                "",
+                0,
+                secs(30),
+            ),
+            (
+                Method::Get,
+                429,
+                rate_limit(&[("retry-after", "5")]),
+                "",
+                3,
+                secs(5),
+            ),
+            // Exhausted primary rate limit.
+            (
+                Method::Post,
+                403,
+                rate_limit(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1700000100"),
+                ]),
+                "API rate limit exceeded",
+                0,
+                secs(101),
+            ),
+            (
+                Method::Get,
+                403,
+                rate_limit(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1700003600"),
+                ]),
+                "API rate limit exceeded",
+                0,
+                None,
+            ),
+            // Secondary rate limit without Retry-After.
+            (
+                Method::Post,
+                403,
+                RateLimit::default(),
+                "You have exceeded a secondary rate limit.",
+                1,
+                secs(120),
+            ),
+            // Permissions are not retried.
+            (
+                Method::Get,
+                403,
+                rate_limit(&[("x-ratelimit-remaining", "4000")]),
+                "Resource not accessible by integration",
+                0,
+                None,
+            ),
+            (Method::Get, 404, RateLimit::default(), "Not Found", 0, None),
+            (Method::Post, 502, RateLimit::default(), "", 0, None),
+            (
+                Method::Get,
+                403,
+                rate_limit(&[("retry-after", "30")]),
+                "",
+                4,
+                None,
+            ),
+        ];
+        for (method, status, rate_limit, message, attempt, expected) in cases {
+            assert_eq!(
+                retry_delay(method, status, &rate_limit, message, attempt, now),
+                expected,
+                "{} {} {:?} {:?} attempt {}",
+                method,
+                status,
+                rate_limit,
+                message,
+                attempt
+            );
+        }
+
+        for attempt in 0..MAX_ATTEMPTS - 1 {
+            let wait =
+                retry_delay(Method::Get, 503, &RateLimit::default(), "", attempt, now).unwrap();
+            let base = Duration::from_secs(1) * 2u32.pow(attempt);
+            assert!(
+                base <= wait && wait < base * 2,
+                "attempt {}: {:?}",
+                attempt,
+                wait
+            );
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Context;
+use octocrab::{Octocrab, OctocrabBuilder};
+use once_cell::sync::OnceCell;
+
+/// The urls of the GitHub instance, github.com or a GitHub Enterprise Server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub api_url: String,
+    pub server_url: String,
+    pub graphql_url: String,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self {
+            api_url: "https://api.github.com".to_string(),
+            server_url: "https://github.com".to_string(),
+            graphql_url: "https://api.github.com/graphql".to_string(),
+        }
+    }
+}
+
+impl Endpoint {
+    /// Returns the endpoint of the instance at `server_url`, e.g. `https://ghes.example.com`.
+    pub fn from_server_url(server_url: impl AsRef<str>) -> Self {
+        let server_url = server_url.as_ref().trim_end_matches('/');
+        if server_url == "https://github.com" {
+            return Self::default();
+        }
+        Self {
+            api_url: format!("{}/api/v3", server_url),
+            server_url: server_url.to_string(),
+            graphql_url: format!("{}/api/graphql", server_url),
+        }
+    }
+
+    pub fn from_context(ctx: &actions::Context) -> Self {
+        Self {
+            api_url: ctx.api_url.trim_end_matches('/').to_string(),
+            server_url: ctx.server_url.trim_end_matches('/').to_string(),
+            graphql_url: ctx.graphql_url.clone(),
+        }
+    }
+
+    /// Reads the endpoint from the variables the runner sets, which outside of the
+    /// runner default to the urls of the instance at `GITHUB_SERVER_URL`.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|x: &String| !x.is_empty());
+        let endpoint = var("GITHUB_SERVER_URL")
+            .map(Self::from_server_url)
+            .unwrap_or_default();
+        Self {
+            api_url: var("GITHUB_API_URL")
+                .map(|x| x.trim_end_matches('/').to_string())
+                .unwrap_or(endpoint.api_url),
+            server_url: endpoint.server_url,
+            graphql_url: var("GITHUB_GRAPHQL_URL").unwrap_or(endpoint.graphql_url),
+        }
+    }
+
+    /// Returns the url git clones `owner/repo` from.
+    pub fn clone_url(&self, owner: impl AsRef<str>, repo: impl AsRef<str>) -> String {
+        format!(
+            "{}/{}/{}.git",
+            self.server_url,
+            owner.as_ref(),
+            repo.as_ref()
+        )
+    }
+
+    /// Returns the base url of the API, which routes are joined to.
+    fn api_base(&self) -> String {
+        // Without the trailing slash the last segment, e.g. `v3` of `/api/v3`, is replaced.
+        format!("{}/", self.api_url)
+    }
+
+    /// Returns a builder of API clients.
+    pub fn client_builder(&self) -> anyhow::Result<OctocrabBuilder> {
+        Octocrab::builder()
+            .base_url(self.api_base().as_str())
+            .with_context(|| format!("invalid api url {}", self.api_url))
+    }
+}
+
+static ENDPOINT: OnceCell<Endpoint> = OnceCell::new();
+
+/// Sets the endpoint. Must be called before the first use of `endpoint()`.
+pub fn init_endpoint(endpoint: Endpoint) {
+    if ENDPOINT.set(endpoint).is_err() {
+        panic!("endpoint is already initialised");
+    }
+}
+
+/// Returns the endpoint, github.com unless set otherwise.
+pub fn endpoint() -> &'static Endpoint {
+    ENDPOINT.get_or_init(Endpoint::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() {
+        let cases = vec![
+            (
+                "https://github.com/",
+                Endpoint::default(),
+                "https://github.com/0x5459/cherrypicker-action.git",
+            ),
+            (
+                "https://ghes.example.com",
+                Endpoint {
+                    api_url: "https://ghes.example.com/api/v3".to_string(),
+                    server_url: "https://ghes.example.com".to_string(),
+                    graphql_url: "https://ghes.example.com/api/graphql".to_string(),
+                },
+                "https://ghes.example.com/0x5459/cherrypicker-action.git",
+            ),
+            // A local mock of the instance.
+            (
+                "http://127.0.0.1:8080",
+                Endpoint {
+                    api_url: "http://127.0.0.1:8080/api/v3".to_string(),
+                    server_url: "http://127.0.0.1:8080".to_string(),
+                    graphql_url: "http://127.0.0.1:8080/api/graphql".to_string(),
+                },
+                "http://127.0.0.1:8080/0x5459/cherrypicker-action.git",
+            ),
+        ];
+        for (server_url, expected, clone_url) in cases {
+            let endpoint = Endpoint::from_server_url(server_url);
+            assert_eq!(endpoint, expected, "server_url: {}", server_url);
+            assert_eq!(
+                endpoint.clone_url("0x5459", "cherrypicker-action"),
+                clone_url,
+                "server_url: {}",
+                server_url
+            );
+            assert_eq!(endpoint.api_base(), format!("{}/", expected.api_url));
+        }
+    }
+}
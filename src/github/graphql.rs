@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+
+use super::{backport_branch, client, endpoint};
+
+// Pull requests or branches per query, keeping queries well within the resource limits.
+const BATCH_SIZE: usize = 50;
+
+/// The metadata of a pull request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullInfo {
+    pub merge_commit: Option<String>,
+    pub labels: Vec<String>,
+    // The number of commits.
+    pub commit_count: usize,
+    // The issues closed by merging the pull request.
+    pub closing_issues: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    // The path of the field of the error, from the root of the response.
+    #[serde(default)]
+    path: Vec<serde_json::Value>,
+}
+
+impl ResponseError {
+    // Whether the pull request aliased `prN` does not exist, which only leaves it null.
+    fn is_missing_pull(&self) -> bool {
+        let alias = match self.path.as_slice() {
+            [repository, alias] if repository == "repository" => alias.as_str(),
+            _ => None,
+        };
+        let number = alias.and_then(|alias| alias.strip_prefix("pr"));
+        self.kind.as_deref() == Some("NOT_FOUND")
+            && matches!(number, Some(n) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    }
+}
+
+impl<T> Response<T> {
+    // Returns the data, failing on any error other than a missing pull request.
+    fn into_data(self) -> anyhow::Result<T> {
+        let errors = self
+            .errors
+            .iter()
+            .filter(|error| !error.is_missing_pull())
+            .map(|error| error.message.as_str())
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            bail!("graphql query failed: {}", errors.join("; "));
+        }
+        self.data.context("graphql response has no data")
+    }
+}
+
+#[derive(Deserialize)]
+struct Data<T> {
+    repository: T,
+}
+
+#[derive(Deserialize)]
+struct Nodes<T> {
+    nodes: Vec<T>,
+}
+
+// The first page of a connection, with the size of all of it.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page<T> {
+    nodes: Vec<T>,
+    total_count: usize,
+}
+
+impl<T> Page<T> {
+    // Returns the nodes, warning if there are more than what fit in the page.
+    fn into_nodes(self, pull_number: u64, what: &str) -> Vec<T> {
+        if self.total_count > self.nodes.len() {
+            tracing::warn!(
+                "pull request #{} has {} {}, only the first {} are considered",
+                pull_number,
+                self.total_count,
+                what,
+                self.nodes.len()
+            );
+        }
+        self.nodes
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawPull {
+    number: u64,
+    merge_commit: Option<Oid>,
+    labels: Page<Name>,
+    commits: Count,
+    closing_issues_references: Page<Number>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawBackport {
+    number: u64,
+    state: BackportState,
+    head_repository_owner: Option<Login>,
+}
+
+/// State of a backport pull request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BackportState {
+    Open,
+    Closed,
+    Merged,
+}
+
+/// A backport pull request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backport {
+    pub number: u64,
+    pub state: BackportState,
+}
+
+#[derive(Deserialize)]
+struct Oid {
+    oid: String,
+}
+
+#[derive(Deserialize)]
+struct Name {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Count {
+    total_count: usize,
+}
+
+#[derive(Deserialize)]
+struct Number {
+    number: u64,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    login: String,
+}
+
+impl From<RawPull> for PullInfo {
+    fn from(pull: RawPull) -> Self {
+        let number = pull.number;
+        Self {
+            merge_commit: pull.merge_commit.map(|commit| commit.oid),
+            labels: pull
+                .labels
+                .into_nodes(number, "labels")
+                .into_iter()
+                .map(|x| x.name)
+                .collect(),
+            commit_count: pull.commits.total_count,
+            closing_issues: pull
+                .closing_issues_references
+                .into_nodes(number, "linked issues")
+                .into_iter()
+                .map(|x| x.number)
+                .collect(),
+        }
+    }
+}
+
+const PULL_FRAGMENT: &str = "fragment pull on PullRequest {
+  number
+  mergeCommit { oid }
+  labels(first: 100) { nodes { name } totalCount }
+  commits { totalCount }
+  closingIssuesReferences(first: 100) { nodes { number } totalCount }
+}";
+
+/// Builds a query of the pull requests `numbers`, aliased `pr0`, `pr1`, ... in order.
+fn pulls_query(numbers: &[u64]) -> String {
+    let pulls = numbers
+        .iter()
+        .enumerate()
+        .map(|(i, number)| {
+            format!(
+                "    pr{}: pullRequest(number: {}) {{ ...pull }}\n",
+                i, number
+            )
+        })
+        .collect::<String>();
+    format!(
+        "query($owner: String!, $repo: String!) {{\n  repository(owner: $owner, name: $repo) {{\n{}  }}\n}}\n{}",
+        pulls, PULL_FRAGMENT
+    )
+}
+
+/// Builds a query of the pull requests from the head branch `$head{i}` into the base
+/// branch `$base{i}`, aliased `b{i}`, for `count` pairs of branches.
+fn backports_query(count: usize) -> String {
+    let variables = (0..count)
+        .map(|i| format!(", $head{i}: String!, $base{i}: String!", i = i))
+        .collect::<String>();
+    let pulls = (0..count)
+        .map(|i| {
+            format!(
+                "    b{i}: pullRequests(headRefName: $head{i}, baseRefName: $base{i}, first: 100, \
+                 orderBy: {{field: CREATED_AT, direction: DESC}}) \
+                 {{ nodes {{ number state headRepositoryOwner {{ login }} }} }}\n",
+                i = i
+            )
+        })
+        .collect::<String>();
+    format!(
+        "query($owner: String!, $repo: String!{}) {{\n  repository(owner: $owner, name: $repo) {{\n{}  }}\n}}\n",
+        variables, pulls
+    )
+}
+
+/// Sends `query` to the GraphQL API of the endpoint, failing on any error in the response
+/// but the pull requests that were not found.
+async fn query<T: DeserializeOwned>(
+    query: String,
+    variables: serde_json::Value,
+) -> anyhow::Result<T> {
+    let resp: Response<T> = client::post(
+        &endpoint().graphql_url,
+        &json!({ "query": query, "variables": variables }),
+    )
+    .await
+    .context("send graphql query")?;
+    resp.into_data()
+}
+
+/// Takes the pull requests aliased `pr0` to `pr{count - 1}` out of `repository`, in order,
+/// with None for those that were not found.
+fn collect_pulls(
+    mut repository: HashMap<String, Option<RawPull>>,
+    count: usize,
+) -> Vec<Option<PullInfo>> {
+    (0..count)
+        .map(|i| {
+            repository
+                .remove(&format!("pr{}", i))
+                .flatten()
+                .map(PullInfo::from)
+        })
+        .collect()
+}
+
+/// Returns the metadata of the pull requests `numbers` of owner/repo, in order, with None
+/// for those that were not found, with a single request per batch of pull requests.
+pub async fn pull_infos(
+    owner: &str,
+    repo: &str,
+    numbers: &[u64],
+) -> anyhow::Result<Vec<Option<PullInfo>>> {
+    let mut infos = Vec::with_capacity(numbers.len());
+    for batch in numbers.chunks(BATCH_SIZE) {
+        let data: Data<HashMap<String, Option<RawPull>>> =
+            query(pulls_query(batch), json!({ "owner": owner, "repo": repo }))
+                .await
+                .context("query pull requests")?;
+        infos.extend(collect_pulls(data.repository, batch.len()));
+    }
+    Ok(infos)
+}
+
+/// Returns the metadata of the pull request `number` of owner/repo.
+pub async fn pull_info(owner: &str, repo: &str, number: u64) -> anyhow::Result<PullInfo> {
+    pull_infos(owner, repo, &[number])
+        .await?
+        .pop()
+        .flatten()
+        .with_context(|| format!("pull request #{} not found", number))
+}
+
+/// Returns the latest backports of `pull_number` onto each of `target_branches` opened
+/// from the fork of `forking_user`, whether they are still open or not, by target branch.
+pub async fn find_backports(
+    forking_user: &str,
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+    target_branches: &[String],
+) -> anyhow::Result<HashMap<String, Backport>> {
+    let mut backports = HashMap::new();
+    for batch in target_branches.chunks(BATCH_SIZE) {
+        let mut variables = json!({ "owner": owner, "repo": repo });
+        for (i, target_branch) in batch.iter().enumerate() {
+            variables[format!("head{}", i)] = json!(backport_branch(pull_number, target_branch));
+            variables[format!("base{}", i)] = json!(target_branch);
+        }
+        let mut data: Data<HashMap<String, Nodes<RawBackport>>> =
+            query(backports_query(batch.len()), variables)
+                .await
+                .context("query backports")?;
+        for (i, target_branch) in batch.iter().enumerate() {
+            let backport = data
+                .repository
+                .remove(&format!("b{}", i))
+                .into_iter()
+                .flat_map(|x| x.nodes)
+                .find(|backport| {
+                    matches!(
+                        &backport.head_repository_owner,
+                        Some(owner) if owner.login.eq_ignore_ascii_case(forking_user)
+                    )
+                });
+            if let Some(backport) = backport {
+                backports.insert(
+                    target_branch.clone(),
+                    Backport {
+                        number: backport.number,
+                        state: backport.state,
+                    },
+                );
+            }
+        }
+    }
+    Ok(backports)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_pulls_query() {
+        assert_eq!(
+            pulls_query(&[12, 34]),
+            format!(
+                "query($owner: String!, $repo: String!) {{
+  repository(owner: $owner, name: $repo) {{
+    pr0: pullRequest(number: 12) {{ ...pull }}
+    pr1: pullRequest(number: 34) {{ ...pull }}
+  }}
+}}
+{}",
+                PULL_FRAGMENT
+            )
+        );
+        assert!(backports_query(2).starts_with(
+            "query($owner: String!, $repo: String!, $head0: String!, $base0: String!, $head1: String!, $base1: String!) {"
+        ));
+    }
+
+    #[test]
+    fn test_collect_pulls() {
+        let resp: Response<Data<HashMap<String, Option<RawPull>>>> =
+            serde_json::from_value(json!({
+                "data": {
+                    "repository": {
+                        "pr1": {
+                            "number": 34,
+                            "mergeCommit": null,
+                            "labels": { "nodes": [], "totalCount": 0 },
+                            "commits": { "totalCount": 101 },
+                            "closingIssuesReferences": { "nodes": [], "totalCount": 0 }
+                        },
+                        "pr0": {
+                            "number": 12,
+                            "mergeCommit": { "oid": "ccc" },
+                            "labels": {
+                                "nodes": [{ "name": "needs-cherry-pick-release-1.0" }],
+                                "totalCount": 1
+                            },
+                            "commits": { "totalCount": 2 },
+                            "closingIssuesReferences": {
+                                "nodes": [{ "number": 7 }],
+                                "totalCount": 1
+                            }
+                        },
+                        "pr2": null
+                    }
+                }
+            }))
+            .unwrap();
+        assert!(resp.errors.is_empty());
+
+        assert_eq!(
+            collect_pulls(resp.data.unwrap().repository, 4),
+            vec![
+                Some(PullInfo {
+                    merge_commit: Some("ccc".to_string()),
+                    labels: vec!["needs-cherry-pick-release-1.0".to_string()],
+                    commit_count: 2,
+                    closing_issues: vec![7],
+                }),
+                Some(PullInfo {
+                    merge_commit: None,
+                    labels: vec![],
+                    commit_count: 101,
+                    closing_issues: vec![],
+                }),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_into_data() {
+        let not_found = |path: serde_json::Value| {
+            json!({
+                "type": "NOT_FOUND",
+                "path": path,
+                "message": "Could not resolve to a PullRequest with the number of 99."
+            })
+        };
+        let cases = vec![
+            (vec![not_found(json!(["repository", "pr1"]))], true),
+            (
+                vec![
+                    not_found(json!(["repository", "pr0"])),
+                    not_found(json!(["repository", "pr12"])),
+                ],
+                true,
+            ),
+            (vec![not_found(json!(["repository"]))], false),
+            (vec![not_found(json!(["repository", "b0"]))], false),
+            (vec![not_found(json!(["repository", "pr"]))], false),
+            (
+                vec![json!({
+                    "type": "FORBIDDEN",
+                    "path": ["repository", "pr1"],
+                    "message": "Resource not accessible by integration"
+                })],
+                false,
+            ),
+            (
+                vec![json!({ "message": "Something went wrong while executing your query." })],
+                false,
+            ),
+        ];
+        for (errors, ok) in cases {
+            let resp: Response<Data<HashMap<String, Option<RawPull>>>> =
+                serde_json::from_value(json!({
+                    "data": { "repository": { "pr0": null, "pr1": null } },
+                    "errors": errors
+                }))
+                .unwrap();
+            assert_eq!(resp.into_data().is_ok(), ok, "{:?}", errors);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{io, path::PathBuf};
+
+use tokio::fs;
+
+use super::client::{self, ApiError};
+
+const PATCH_MEDIA_TYPE: &str = "application/vnd.github.patch";
+
+#[derive(thiserror::Error, Debug)]
+pub enum PatchError {
+    #[error("fetch the patch of #{pull_number}")]
+    Fetch {
+        pull_number: u64,
+        #[source]
+        source: ApiError,
+    },
+    // GitHub does not render patches of pull requests with too many files or lines.
+    #[error("the patch of #{pull_number} is too large to be fetched from GitHub")]
+    TooLarge { pull_number: u64 },
+    #[error("the patch of #{pull_number} is empty")]
+    Empty { pull_number: u64 },
+    #[error("the patch of #{pull_number} is not an mbox patch: {first_line:?}")]
+    NotMbox {
+        pull_number: u64,
+        first_line: String,
+    },
+    #[error("write patch file {}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// Returns true if `patch` is in the mbox format `git am` applies, i.e. starts with the
+/// `From <sha> <date>` separator and has the headers of the first commit.
+fn is_mbox(patch: &str) -> bool {
+    let mut lines = patch.lines();
+    let sha = match lines.next().and_then(|line| line.strip_prefix("From ")) {
+        Some(rest) => rest.split(' ').next().unwrap_or_default(),
+        None => return false,
+    };
+    sha.len() == 40
+        && sha.chars().all(|c| c.is_ascii_hexdigit())
+        && lines
+            .take_while(|line| !line.is_empty())
+            .any(|line| line.starts_with("Subject: "))
+}
+
+/// Checks that `patch` of `pull_number` can be applied with `git am`. The patch is only
+/// read as text for that, it may have files in other encodings than UTF-8.
+fn validate(pull_number: u64, patch: &[u8]) -> Result<(), PatchError> {
+    let patch = String::from_utf8_lossy(patch);
+    if patch.trim().is_empty() {
+        return Err(PatchError::Empty { pull_number });
+    }
+    if !is_mbox(&patch) {
+        return Err(PatchError::NotMbox {
+            pull_number,
+            first_line: patch.lines().next().unwrap_or_default().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Downloads the patch of `pull_number` through the API with the credentials of the
+/// client, and writes it to `path`, which is returned.
+pub async fn download_patch(
+    owner: impl Into<String>,
+    repo: impl Into<String>,
+    pull_number: u64,
+    path: impl Into<PathBuf>,
+) -> Result<PathBuf, PatchError> {
+    let owner = owner.into();
+    let repo = repo.into();
+    let path = path.into();
+
+    let patch = client::get_raw(
+        format!("repos/{}/{}/pulls/{}", owner, repo, pull_number),
+        PATCH_MEDIA_TYPE,
+    )
+    .await
+    .map_err(|source| match source.status() {
+        Some(406) => PatchError::TooLarge { pull_number },
+        _ => PatchError::Fetch {
+            pull_number,
+            source,
+        },
+    })?;
+    validate(pull_number, &patch)?;
+
+    fs::write(&path, patch)
+        .await
+        .map_err(|source| PatchError::Write {
+            path: path.clone(),
+            source,
+        })?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let patch = "From 3a8e9f4c1f0d2b7a6e5c4d3b2a1f0e9d8c7b6a5f Mon Sep 17 00:00:00 2001
+From: Octocat <octocat@github.com>
+Date: Mon, 2 Jan 2023 15:04:05 +0800
+Subject: [PATCH] Fix the frobnicator
+
+---
+ src/lib.rs | 2 +-
+ 1 file changed, 1 insertion(+), 1 deletion(-)
+";
+        assert!(validate(1, patch.as_bytes()).is_ok());
+        // A file in Latin-1 does not make it invalid.
+        let latin1 = [patch.as_bytes(), b"-caf\xe9\n+caf\xe8\n"].concat();
+        assert!(validate(1, &latin1).is_ok());
+
+        assert!(matches!(
+            validate(1, b" \n"),
+            Err(PatchError::Empty { pull_number: 1 })
+        ));
+        assert!(matches!(
+            validate(1, b"<!DOCTYPE html>\n<html>Not Found</html>\n"),
+            Err(PatchError::NotMbox { first_line, .. }) if first_line == "<!DOCTYPE html>"
+        ));
+        assert!(matches!(
+            validate(1, b"diff --git a/src/lib.rs b/src/lib.rs\n"),
+            Err(PatchError::NotMbox { .. })
+        ));
+        // Headers without a subject.
+        assert!(matches!(
+            validate(
+                1,
+                b"From 3a8e9f4c1f0d2b7a6e5c4d3b2a1f0e9d8c7b6a5f Mon Sep 17 00:00:00 2001\n\nSubject: x\n"
+            ),
+            Err(PatchError::NotMbox { .. })
+        ));
+    }
+}
@@ -1,95 +1,1047 @@
-use actions::get_input_required;
+use std::{collections::HashMap, io, path::Path};
+
+use actions::{get_input, get_input_required, set_output, RepoRef, Summary};
 use anyhow::{Context, Result};
-use github::{client_for, download_patch, ensure_fork};
-use octocrab::models::events::payload::{
-    EventPayload, IssueCommentEventAction, IssueCommentEventPayload, PullRequestEventPayload,
+use chain::Source;
+use chrono::{Duration, Utc};
+use commands::{parse_commands, CherryPickCommand, Command, CommandError, PickOptions, Strategy};
+use config::{config, Config};
+use event::PullRequestEvent;
+use git::{CherryPickOptions, Git, Workspace};
+use github::{
+    add_labels, authenticate, backport_branch, create_comment, create_issue, create_pull,
+    download_patch, endpoint, ensure_fork, ensure_label, find_backport, find_backports,
+    forking_user, get_pull, init_auth, init_endpoint, is_collaborator, is_failed, is_picked,
+    list_merged_since, parse_backport_branch, pull_info, pull_infos, react, remove_label, unreact,
+    usage, workspace_for, App, Auth, Endpoint,
+};
+use itertools::Itertools;
+use logit::Logit;
+use octocrab::models::{
+    events::payload::{IssueCommentEventAction, IssueCommentEventPayload},
+    pulls::PullRequest,
+    reactions::ReactionContent,
+    IssueState,
 };
 use tokio::task::spawn_blocking;
+use tracing::Instrument;
+use util::{match_label, match_picked_label, pending_targets};
 
+mod chain;
+mod cli;
+mod commands;
 mod config;
+mod event;
 mod github;
+mod server;
 mod util;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let repo_token: String = get_input_required("repo-token")?;
-    octocrab::initialise(octocrab::Octocrab::builder().personal_token(repo_token))
-        .context("initialise octocrab")?;
+    logit::init();
+
+    // Without arguments, runs as the action in the environment of the workflow.
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        return cli::run(args).await;
+    }
 
     let ctx = spawn_blocking(actions::Context::from_env)
-        .await
+        .await?
         .context("create actions context")?;
 
-    match ctx.payload {
-        EventPayload::IssueCommentEvent(evt) => on_issue_comment(evt).await,
-        EventPayload::PullRequestEvent(evt) => on_pull_request(evt).await,
-        _ => {}
+    init_endpoint(Endpoint::from_context(&ctx));
+    let auth = match get_input::<u64>("app-id")? {
+        Some(app_id) => {
+            let private_key: String = get_input_required("app-private-key")?;
+            for line in private_key
+                .lines()
+                .filter(|line| !line.starts_with("-----"))
+            {
+                actions::add_mask(line);
+            }
+            Auth::App(App::new(app_id, private_key)?)
+        }
+        None => Auth::Token(get_input_required("repo-token")?),
+    };
+    init_auth(auth);
+    config::init_config(Config::from_actions().context("read inputs")?);
+
+    let result = dispatch(&ctx.event_name, ctx.repo(), ctx.raw_payload).await;
+    publish_usage();
+    result
+}
+
+/// Records the API usage of the run in the job summary.
+fn publish_usage() {
+    let usage = usage();
+    if usage.requests == 0 {
+        return;
+    }
+    let remaining = match (usage.remaining, usage.limit) {
+        (Some(remaining), Some(limit)) => format!("{} of {}", remaining, limit),
+        _ => "unknown".to_string(),
+    };
+    let _ = Summary::new()
+        .heading("GitHub API usage", 3)
+        .table(
+            ["Requests", "Retries", "Waited", "Rate limit remaining"],
+            [[
+                usage.requests.to_string(),
+                usage.retries.to_string(),
+                format!("{}s", usage.waited.as_secs()),
+                remaining,
+            ]],
+        )
+        .write()
+        .logit_warn("write job summary");
+}
+
+/// Handles the webhook event `event_name` in `repo`, decoding its raw `payload` as needed.
+async fn dispatch(event_name: &str, repo: RepoRef, payload: serde_json::Value) -> Result<()> {
+    authenticate(&repo.owner, &repo.repo).await?;
+    match event_name {
+        "issue_comment" => {
+            let event = serde_json::from_value(payload).context("decode event payload")?;
+            on_issue_comment(repo, event).await
+        }
+        // PRs from forks only get a token with write permissions in `pull_request_target`
+        // workflows, which run in the context of the base repository.
+        "pull_request" | "pull_request_target" | "issues" => {
+            match PullRequestEvent::from_payload(payload).context("decode event payload")? {
+                Some(event) => on_pull_request(repo, event).await,
+                None => Ok(()),
+            }
+        }
+        // Catches up on events that were missed, e.g. when the action failed.
+        "workflow_dispatch" | "schedule" => sweep(repo).await,
+        _ => Ok(()),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn sweep(repo: RepoRef) -> Result<()> {
+    let RepoRef { owner, repo } = repo;
+    let since = Utc::now() - Duration::days(config().sweep_lookback_days.into());
+    let mut pending = Vec::new();
+    for pr in list_merged_since(&owner, &repo, since).await? {
+        let (failed, targets) = pending_targets(&label_names(&pr));
+        // Failures were already reported, they are retried on request.
+        if !failed.is_empty() {
+            tracing::info!(
+                pull_number = pr.number,
+                "skipping failed cherry-picks to {}",
+                failed.join(", ")
+            );
+        }
+        if !targets.is_empty() {
+            pending.push((pr, targets));
+        }
+    }
+    tracing::info!(
+        "found {} pull requests merged since {} with pending cherry-picks",
+        pending.len(),
+        since
+    );
+
+    let numbers = pending.iter().map(|(pr, _)| pr.number).collect::<Vec<_>>();
+    let infos = pull_infos(&owner, &repo, &numbers).await?;
+    let rows = pending.iter().zip(&infos).map(|((pr, targets), info)| {
+        let (commits, issues) = match info {
+            Some(info) => (
+                info.commit_count.to_string(),
+                info.closing_issues
+                    .iter()
+                    .map(|issue| format!("#{}", issue))
+                    .join(", "),
+            ),
+            None => ("unknown".to_string(), "unknown".to_string()),
+        };
+        vec![
+            format!("#{}", pr.number),
+            commits,
+            issues,
+            targets.join(", "),
+        ]
+    });
+    let mut summary = Summary::new().heading("Pending cherry-picks", 3).table(
+        [
+            "Pull request",
+            "Commits",
+            "Linked issues",
+            "Target branches",
+        ],
+        rows,
+    );
+    if config().dry_run {
+        summary = summary.paragraph("Dry run, nothing was picked.");
+    }
+    let _ = summary.write().logit_warn("write job summary");
+    if config().dry_run {
+        return Ok(());
+    }
+
+    let mut outcomes = Vec::new();
+    let mut failed = Vec::new();
+    for (pr, targets) in pending {
+        let targets = targets
+            .into_iter()
+            .map(|branch| Target {
+                branch,
+                options: PickOptions::default(),
+                retry: false,
+            })
+            .collect();
+        match cherry_pick_all(&owner, &repo, &pr, targets).await {
+            Ok(picked) => outcomes.extend(picked),
+            Err(err) => {
+                tracing::error!(pull_number = pr.number, "sweep failed: {:#}", err);
+                failed.push(format!("#{}", pr.number));
+            }
+        }
+    }
+    publish_outputs(&outcomes);
+    if !failed.is_empty() {
+        anyhow::bail!("failed to cherry-pick {}", failed.join(", "));
     }
     Ok(())
 }
 
-async fn on_issue_comment(event: Box<IssueCommentEventPayload>) {
+#[tracing::instrument(skip_all, fields(pull_number = event.issue.number))]
+async fn on_issue_comment(repo: RepoRef, event: Box<IssueCommentEventPayload>) -> Result<()> {
     // Only consider new comments in PRs.
-    if event.action != IssueCommentEventAction::Created || event.issue.pull_request.is_none() {}
+    if event.action != IssueCommentEventAction::Created || event.issue.pull_request.is_none() {
+        return Ok(());
+    }
+
+    // Invitations are not handled by the cherry-picker.
+    let mut commands = parse_commands(event.comment.body.as_deref().unwrap_or_default());
+    commands.retain(|command| command != &Ok(Command::Invite));
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let RepoRef { owner, repo } = repo;
+    let comment_id = event.comment.id;
+
+    // Let the commenter know the command was seen, picking may take minutes.
+    let eyes = react(&owner, &repo, comment_id, ReactionContent::Eyes)
+        .await
+        .logit_warn("acknowledge command")
+        .ok();
+
+    let result = run_commands(
+        &owner,
+        &repo,
+        event.issue.number,
+        &event.comment.user.login,
+        commands,
+    )
+    .await;
+
+    if let Some(eyes) = eyes {
+        let _ = unreact(&owner, &repo, comment_id, eyes.id)
+            .await
+            .logit_warn("remove acknowledgement");
+    }
+    let content = match result {
+        Ok(true) => ReactionContent::Rocket,
+        _ => ReactionContent::Confused,
+    };
+    let _ = react(&owner, &repo, comment_id, content)
+        .await
+        .logit_warn("react to command");
+
+    result.map(|_| ())
+}
+
+/// Runs the commands of a comment on `pull_number`. Returns false if some of them
+/// were rejected or failed, which has been reported on the PR.
+async fn run_commands(
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+    commenter: &str,
+    commands: Vec<Result<Command, CommandError>>,
+) -> Result<bool> {
+    if !config().allow_all && !is_collaborator(owner, repo, commenter).await? {
+        create_comment(
+            owner,
+            repo,
+            pull_number,
+            format!(
+                "@{} only collaborators are allowed to cherry-pick.",
+                commenter
+            ),
+        )
+        .await?;
+        return Ok(false);
+    }
+
+    let mut errors = Vec::new();
+    let mut targets = Vec::new();
+    let mut cancels = Vec::new();
+    let mut status = false;
+    for command in commands {
+        match command {
+            Ok(Command::CherryPick(CherryPickCommand::Pick {
+                target_branch,
+                options,
+            })) => targets.push(Target {
+                branch: target_branch,
+                options,
+                retry: false,
+            }),
+            Ok(Command::CherryPick(CherryPickCommand::Retry {
+                target_branch,
+                options,
+            })) => targets.push(Target {
+                branch: target_branch,
+                options,
+                retry: true,
+            }),
+            Ok(Command::CherryPick(CherryPickCommand::Cancel { target_branch })) => {
+                cancels.push(target_branch)
+            }
+            Ok(Command::CherryPick(CherryPickCommand::Status)) => status = true,
+            Ok(Command::Invite) => {}
+            Err(error) => errors.push(format!("- {}", error)),
+        }
+    }
+    let mut succeeded = errors.is_empty();
+    if !errors.is_empty() {
+        create_comment(
+            owner,
+            repo,
+            pull_number,
+            format!("Invalid cherry-pick command:\n{}", errors.join("\n")),
+        )
+        .await?;
+    }
+
+    let pr = get_pull(owner, repo, pull_number).await?;
+
+    if !cancels.is_empty() {
+        cancel(owner, repo, &pr, &cancels).await?;
+    }
+
+    if !targets.is_empty() {
+        if pr.merged_at.is_some() {
+            let outcomes = cherry_pick_all(owner, repo, &pr, targets).await?;
+            publish_outputs(&outcomes);
+            succeeded &= all_picked(&outcomes);
+        } else if pr.state == Some(IssueState::Closed) || config().label_prefix.is_empty() {
+            create_comment(
+                owner,
+                repo,
+                pull_number,
+                "Only merged pull requests can be cherry-picked.",
+            )
+            .await?;
+            succeeded = false;
+        } else {
+            defer_cherry_pick(owner, repo, pull_number, targets).await?;
+        }
+    }
+
+    if status {
+        report_status(owner, repo, pull_number).await?;
+    }
+    Ok(succeeded)
+}
+
+/// Records the target branches requested on an open PR as `label_prefix` labels,
+/// which `on_pull_request` picks once the PR is merged. Deferred picks use the
+/// default options, the user is told when the requested ones are dropped.
+async fn defer_cherry_pick(
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+    targets: Vec<Target>,
+) -> Result<()> {
+    let labels = targets
+        .iter()
+        .map(|target| format!("{}{}", config().label_prefix, target.branch))
+        .collect::<Vec<_>>();
+    for label in &labels {
+        ensure_label(owner, repo, label).await?;
+    }
+    add_labels(owner, repo, pull_number, &labels).await?;
+
+    let with_options = targets
+        .iter()
+        .filter(|target| target.options != PickOptions::default())
+        .map(|target| target.branch.as_str())
+        .collect::<Vec<_>>();
+    if with_options.is_empty() {
+        return Ok(());
+    }
+    create_comment(
+        owner,
+        repo,
+        pull_number,
+        format!(
+            "The cherry-picks to {} will use the default options once this pull request is \
+             merged, `--strategy` and `--draft` are ignored until then. To use them, cancel the \
+             cherry-picks with `/cherry-pick cancel <branch>` and request them again after \
+             merging.",
+            with_options.join(", ")
+        ),
+    )
+    .await
+}
+
+/// Cancels the pending cherry-picks of `pr` to the given target branches.
+async fn cancel(owner: &str, repo: &str, pr: &PullRequest, targets: &[String]) -> Result<()> {
+    let (pending, not_pending): (Vec<_>, Vec<_>) = targets
+        .iter()
+        .cloned()
+        .partition(|target_branch| pr.merged_at.is_none() && is_pending(pr, target_branch));
+
+    remove_pending_labels(owner, repo, pr.number, &pending).await?;
+
+    let mut lines = Vec::new();
+    if !pending.is_empty() {
+        lines.push(format!(
+            "Cancelled the pending cherry-picks to {}.",
+            pending.join(", ")
+        ));
+    }
+    if !not_pending.is_empty() {
+        lines.push(format!(
+            "There are no pending cherry-picks to {}.",
+            not_pending.join(", ")
+        ));
+    }
+    create_comment(owner, repo, pr.number, lines.join("\n")).await
+}
+
+/// Comments a table of the requested and picked target branches of `pull_number`.
+async fn report_status(owner: &str, repo: &str, pull_number: u64) -> Result<()> {
+    // Fetched again, as the other commands of the comment may have changed the labels.
+    let pr = pull_info(owner, repo, pull_number).await?;
+    let branches = pr
+        .labels
+        .iter()
+        .filter_map(|label| match_label(label).or_else(|| match_picked_label(label)))
+        .unique()
+        .collect::<Vec<_>>();
+    if branches.is_empty() {
+        return create_comment(
+            owner,
+            repo,
+            pull_number,
+            "No cherry-picks were requested for this pull request.",
+        )
+        .await;
+    }
+
+    let forking_user = forking_user(owner).await?;
+    let backports = find_backports(&forking_user, owner, repo, pull_number, &branches).await?;
+    let mut rows = vec![
+        "| Branch | Status | Backport |".to_string(),
+        "| --- | --- | --- |".to_string(),
+    ];
+    for target_branch in branches {
+        let status = if pr
+            .labels
+            .iter()
+            .any(|label| match_picked_label(label).as_deref() == Some(target_branch.as_str()))
+        {
+            "picked"
+        } else if pr.labels.iter().any(|label| {
+            label.strip_prefix(&config().failed_label_prefix) == Some(target_branch.as_str())
+        }) {
+            "failed"
+        } else {
+            "pending"
+        };
+        let backport = backports
+            .get(&target_branch)
+            .map(|backport| format!("#{}", backport.number))
+            .unwrap_or_default();
+        rows.push(format!("| {} | {} | {} |", target_branch, status, backport));
+    }
+    create_comment(owner, repo, pull_number, rows.join("\n")).await
+}
+
+#[tracing::instrument(skip_all, fields(pull_number = event.number, action = %event.action))]
+async fn on_pull_request(repo: RepoRef, event: PullRequestEvent) -> Result<()> {
+    // Only consider closed PRs, and pick labels added after the PR was closed.
+    let labeled_target = match event.action.as_str() {
+        "closed" => None,
+        "labeled" => match event.label.as_deref().and_then(match_label) {
+            Some(target) => Some(target),
+            None => return Ok(()),
+        },
+        _ => return Ok(()),
+    };
+
+    let RepoRef { owner, repo } = repo;
+    let pr = match event.pull_request {
+        Some(pr) => pr,
+        None => get_pull(&owner, &repo, event.number).await?,
+    };
+    match labeled_target {
+        // Labels on open PRs are picked up once the PR is merged.
+        Some(target) if pr.merged_at.is_some() => {
+            let target = Target {
+                branch: target,
+                options: PickOptions::default(),
+                retry: false,
+            };
+            let outcomes = cherry_pick_all(&owner, &repo, &pr, vec![target]).await?;
+            publish_outputs(&outcomes);
+            Ok(())
+        }
+        Some(_) => Ok(()),
+        None => on_closed(&owner, &repo, &pr).await,
+    }
+}
+
+async fn on_closed(owner: &str, repo: &str, pr: &PullRequest) -> Result<()> {
+    // A merged backport lets the next branches of its chain go.
+    if pr.merged_at.is_some() && !config().chain_backports.is_empty() {
+        if let Some((pull_number, _)) = parse_backport_branch(&pr.head.ref_field) {
+            let original = get_pull(owner, repo, pull_number).await?;
+            let (_, targets) = pending_targets(&label_names(&original));
+            let targets = targets
+                .into_iter()
+                .map(|branch| Target {
+                    branch,
+                    options: PickOptions::default(),
+                    retry: false,
+                })
+                .collect();
+            let outcomes = cherry_pick_all(owner, repo, &original, targets).await?;
+            publish_outputs(&outcomes);
+            return Ok(());
+        }
+    }
+
+    let targets = pr
+        .labels
+        .iter()
+        .flatten()
+        .filter_map(|label| match_label(&label.name))
+        .unique()
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        return Ok(());
+    }
+
+    if pr.merged_at.is_none() {
+        remove_pending_labels(owner, repo, pr.number, &targets).await?;
+        return create_comment(
+            owner,
+            repo,
+            pr.number,
+            format!(
+                "This pull request was closed without being merged, cancelled the pending cherry-picks to {}.",
+                targets.join(", ")
+            ),
+        )
+        .await;
+    }
+
+    let targets = targets
+        .into_iter()
+        .map(|branch| Target {
+            branch,
+            options: PickOptions::default(),
+            retry: false,
+        })
+        .collect();
+    let outcomes = cherry_pick_all(owner, repo, pr, targets).await?;
+    publish_outputs(&outcomes);
+    Ok(())
+}
+
+async fn remove_pending_labels(
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+    targets: &[String],
+) -> Result<()> {
+    for target_branch in targets {
+        remove_label(
+            owner,
+            repo,
+            pull_number,
+            format!("{}{}", config().label_prefix, target_branch),
+        )
+        .await?;
+    }
+    Ok(())
 }
 
-async fn on_pull_request(_issue_comment_event: Box<PullRequestEventPayload>) {}
+/// Returns true if a cherry-pick of `pr` to `target_branch` was requested.
+fn is_pending(pr: &PullRequest, target_branch: &str) -> bool {
+    pr.labels
+        .iter()
+        .flatten()
+        .any(|label| match_label(&label.name).as_deref() == Some(target_branch))
+}
+
+/// A target branch to cherry-pick onto.
+struct Target {
+    branch: String,
+    options: PickOptions,
+    // Specifies whether to pick again even if the target branch was already picked.
+    retry: bool,
+}
+
+/// The result of cherry-picking a PR onto one target branch.
+struct PickOutcome {
+    target_branch: String,
+    // The backport pull request, or why it could not be created.
+    backport: Result<PullRequest, String>,
+}
+
+/// Cherry-picks `pr` onto every target branch, skipping the ones already picked unless
+/// retried. A failed pick is reported on `pr` and does not stop the others. Returns the
+/// outcomes, which are also listed in the job summary, for the caller to publish them as
+/// step outputs once for the whole run.
+///
+/// The branches of `chain_backports` are picked in their order, each one from the merged
+/// backport onto its predecessor, see [`chain::plan`]. A target waiting for that backport
+/// to be merged is left pending, unless retried, in which case it picks from `pr` itself.
+async fn cherry_pick_all(
+    owner: &str,
+    repo: &str,
+    pr: &PullRequest,
+    targets: Vec<Target>,
+) -> Result<Vec<PickOutcome>> {
+    let forking_user = forking_user(owner).await?;
+
+    let chain = &config().chain_backports;
+    let backports = if chain.is_empty() {
+        HashMap::new()
+    } else {
+        find_backports(&forking_user, owner, repo, pr.number, chain).await?
+    };
+    let branches = targets.iter().map(|target| target.branch.clone()).collect();
+    let mut targets = targets
+        .into_iter()
+        .map(|target| (target.branch.clone(), target))
+        .collect::<HashMap<_, _>>();
+
+    let mut outcomes = Vec::new();
+    for (target_branch, source) in chain::plan(&label_names(pr), branches, &backports) {
+        let target = match targets.remove(&target_branch) {
+            Some(x) => x,
+            None => continue,
+        };
+        if is_picked(pr, &target_branch) {
+            if !target.retry {
+                continue;
+            }
+            remove_label(
+                owner,
+                repo,
+                pr.number,
+                format!("{}{}", config().picked_label_prefix, target_branch),
+            )
+            .await
+            .context("remove picked label")?;
+        }
+        let patch_from = match source {
+            Source::Original => pr.number,
+            Source::Backport(number) => number,
+            Source::Wait(_) if target.retry => pr.number,
+            Source::Wait(previous) => {
+                tracing::info!(
+                    "waiting for the backport of #{} to {} to be merged before cherry-picking to {}",
+                    pr.number,
+                    previous,
+                    target_branch
+                );
+                continue;
+            }
+        };
+
+        // Picks may take minutes, refresh the token if it is about to expire.
+        authenticate(owner, repo).await?;
+
+        let span = tracing::info_span!(
+            "cherry_pick",
+            target_branch = %target_branch,
+            patch_from = patch_from
+        );
+        let picked = actions::group(
+            format!("Cherry-pick #{} to {}", pr.number, target_branch),
+            cherry_pick(
+                &forking_user,
+                owner,
+                repo,
+                pr.number,
+                patch_from,
+                &target_branch,
+                &target.options,
+            )
+            .instrument(span),
+        )
+        .await;
+        match picked {
+            Ok(backport) => {
+                let label = format!("{}{}", config().picked_label_prefix, target_branch);
+                ensure_label(owner, repo, &label).await?;
+                add_labels(owner, repo, pr.number, &[label])
+                    .await
+                    .context("add picked label")?;
+                if is_failed(pr, &target_branch) {
+                    remove_label(
+                        owner,
+                        repo,
+                        pr.number,
+                        format!("{}{}", config().failed_label_prefix, target_branch),
+                    )
+                    .await
+                    .context("remove failed label")?;
+                }
+                create_comment(
+                    owner,
+                    repo,
+                    pr.number,
+                    format!(
+                        "New pull request for {} created: #{}",
+                        target_branch, backport.number
+                    ),
+                )
+                .await?;
+                outcomes.push(PickOutcome {
+                    target_branch,
+                    backport: Ok(backport),
+                });
+            }
+            Err(error) => {
+                // Also an error annotation, under Actions.
+                tracing::error!(
+                    "Failed to cherry-pick #{} to {}: {:#}",
+                    pr.number,
+                    target_branch,
+                    error
+                );
+                report_failure(owner, repo, pr.number, &target_branch, &error).await?;
+                outcomes.push(PickOutcome {
+                    target_branch,
+                    backport: Err(format!("{:#}", error)),
+                });
+            }
+        }
+    }
+
+    summarize_outcomes(pr.number, &outcomes);
+    Ok(outcomes)
+}
+
+fn label_names(pr: &PullRequest) -> Vec<String> {
+    pr.labels
+        .iter()
+        .flatten()
+        .map(|label| label.name.clone())
+        .collect()
+}
+
+/// Returns true if none of the picks failed.
+fn all_picked(outcomes: &[PickOutcome]) -> bool {
+    outcomes.iter().all(|outcome| outcome.backport.is_ok())
+}
+
+/// Publishes the backports of all the picks of the run as step outputs, so that later
+/// workflow steps can chain on them.
+fn publish_outputs(outcomes: &[PickOutcome]) {
+    if outcomes.is_empty() {
+        return;
+    }
+
+    let backports = outcomes
+        .iter()
+        .filter_map(|outcome| outcome.backport.as_ref().ok())
+        .collect::<Vec<_>>();
+    let numbers = backports
+        .iter()
+        .map(|backport| backport.number)
+        .collect::<Vec<_>>();
+    let urls = backports
+        .iter()
+        .filter_map(|backport| backport.html_url.as_ref().map(|url| url.as_str()))
+        .collect::<Vec<_>>();
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.backport.is_err())
+        .map(|outcome| outcome.target_branch.as_str())
+        .collect::<Vec<_>>();
+    for (name, value) in [
+        ("backport-numbers", serde_json::to_string(&numbers)),
+        ("backport-urls", serde_json::to_string(&urls)),
+        ("failed-branches", serde_json::to_string(&failed)),
+    ] {
+        let _ = value
+            .map_err(io::Error::from)
+            .and_then(|value| set_output(name, value))
+            .with_logit_warn(|| format!("set output {}", name));
+    }
+}
+
+/// Writes the outcomes of the picks of `pull_number` as a results table in the job summary.
+fn summarize_outcomes(pull_number: u64, outcomes: &[PickOutcome]) {
+    if outcomes.is_empty() {
+        return;
+    }
+
+    let rows = outcomes.iter().map(|outcome| match &outcome.backport {
+        Ok(backport) => vec![
+            outcome.target_branch.clone(),
+            "picked".to_string(),
+            match &backport.html_url {
+                Some(url) => format!("[#{}]({})", backport.number, url),
+                None => format!("#{}", backport.number),
+            },
+        ],
+        Err(error) => vec![
+            outcome.target_branch.clone(),
+            format!("failed: {}", error),
+            String::new(),
+        ],
+    });
+    let _ = Summary::new()
+        .heading(format!("Cherry-pick #{}", pull_number), 3)
+        .table(["Target branch", "Result", "Backport"], rows)
+        .write()
+        .logit_warn("write job summary");
+}
+
+async fn report_failure(
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+    target_branch: &str,
+    error: &anyhow::Error,
+) -> Result<()> {
+    let body = format!(
+        "Failed to cherry-pick #{} to {}:\n```\n{:#}\n```",
+        pull_number, target_branch, error
+    );
+    create_comment(owner, repo, pull_number, &body).await?;
 
+    // Keeps the sweep mode from picking and reporting it again on every run.
+    let label = format!("{}{}", config().failed_label_prefix, target_branch);
+    ensure_label(owner, repo, &label).await?;
+    add_labels(owner, repo, pull_number, &[label])
+        .await
+        .context("add failed label")?;
+
+    if config().create_issue_on_conflict {
+        create_issue(
+            owner,
+            repo,
+            format!(
+                "Failed to cherry-pick #{} to {}",
+                pull_number, target_branch
+            ),
+            body,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Cherry-picks `pull_number` onto `target_branch` and opens the backport pull request,
+/// or returns the one already open from a previous attempt.
+/// The changes are taken from `patch_from`, which is either `pull_number` itself or one of
+/// its merged backports.
 async fn cherry_pick(
     forking_user: impl Into<String>,
     owner: impl Into<String>,
     repo: impl Into<String>,
     pull_number: u64,
+    patch_from: u64,
     target_branch: impl Into<String>,
-) -> anyhow::Result<()> {
+    options: &PickOptions,
+) -> anyhow::Result<PullRequest> {
     let forking_user = forking_user.into();
     let owner = owner.into();
     let repo = repo.into();
     let target_branch = target_branch.into();
 
-    // 1. fork
-    ensure_fork(&forking_user, &owner, &repo)
-        .await
-        .context("ensure fork")?;
+    // 1. fork, unless pushing to the upstream repo
+    if forking_user != owner {
+        ensure_fork(&forking_user, &owner, &repo)
+            .await
+            .context("ensure fork")?;
+    }
 
-    // 2. clone
-    let mut git = client_for(&forking_user, &repo).await;
+    // 2. - 7.
+    let (mut git, local_branch) = apply_backport(
+        &owner,
+        &repo,
+        pull_number,
+        patch_from,
+        &target_branch,
+        options,
+    )
+    .await?;
 
-    // 3. checkout to target branch
-    git.checkout(&target_branch)
-        .await
-        .context("checkout to target branch")?;
+    // Title for GitHub issue/PR.
+    let title = format!("cherry-pick #{} to {}", pull_number, target_branch);
+
+    // 8. push
+    git.push(
+        endpoint().clone_url(&forking_user, &repo),
+        local_branch.clone(),
+        true,
+    )
+    .await
+    .context("push to github")?;
+    let _ = git.close().await.logit_warn("remove workspace");
+
+    // 9. create pr, unless one is still open from a previous attempt
+    if let Some(backport) = find_backport(&forking_user, &owner, &repo, pull_number, &target_branch)
+        .await?
+        .filter(|backport| backport.state == Some(IssueState::Open))
+    {
+        return Ok(backport);
+    }
+
+    let body = if patch_from == pull_number {
+        format!("This is an automated cherry-pick of #{}", pull_number)
+    } else {
+        format!(
+            "This is an automated cherry-pick of #{}, picked from its backport #{}",
+            pull_number, patch_from
+        )
+    };
+    create_pull(
+        &owner,
+        &repo,
+        title,
+        format!("{}:{}", forking_user, local_branch),
+        &target_branch,
+        body,
+        options.draft,
+    )
+    .await
+}
 
-    // 4. checkout -b
-    let local_branch = format!("cherry-pick-{}-to-{}", pull_number, target_branch);
-    git.checkout_new_branch(&local_branch)
+/// Applies the changes of `patch_from` onto a new backport branch off `target_branch`,
+/// in a workspace of owner/repo, see `workspace_for`. Returns the workspace and the
+/// backport branch.
+async fn apply_backport(
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+    patch_from: u64,
+    target_branch: &str,
+    options: &PickOptions,
+) -> anyhow::Result<(Workspace, String)> {
+    // 2. - 3. clone and checkout to target branch
+    let mut git = workspace_for(owner, repo, target_branch).await?;
+
+    // 4. checkout new branch
+    let local_branch = backport_branch(pull_number, target_branch);
+    git.checkout_reset_branch(&local_branch)
         .await
         .context("checkout new branch")?;
 
-    // 5. download patch from github
-    let patch_path = download_patch(owner, repo, pull_number, &target_branch)
-        .await
-        .context("download patch")?;
+    match options.strategy {
+        Strategy::Am => {
+            // 5. generate the patch locally, or else download it from github
+            let patch_path = git.scratch_path(format!("pull-{}.patch", patch_from));
+            if format_pull_patch(&mut git, owner, repo, patch_from, &patch_path)
+                .await
+                .logit_warn("format patch")
+                .is_err()
+            {
+                download_patch(owner, repo, patch_from, &patch_path)
+                    .await
+                    .context("download patch")?;
+            }
 
-    // 6. git config
+            // 6. git config
 
-    // Title for GitHub issue/PR.
-    let title = format!("cherry-pick #{} to {}", pull_number, target_branch);
+            // 7. Try git am --3way localPath.
+            git.am(patch_path).await.with_context(|| {
+                format!(
+                    "apply #{} on top of target branch {}",
+                    patch_from, target_branch
+                )
+            })?;
+        }
+        Strategy::CherryPick => {
+            // 5. fetch the commits of the PR from the upstream repo
+            let range = fetch_pull_commits(&mut git, owner, repo, patch_from).await?;
+            let commits = git.log(&range).await.context("list pull request commits")?;
+            // Oldest first, without the merges of the base branch into the PR, like `am`.
+            let commits = commits
+                .iter()
+                .rev()
+                .filter(|commit| commit.parents.len() <= 1)
+                .map(|commit| &commit.id)
+                .collect::<Vec<_>>();
+            if commits.is_empty() {
+                anyhow::bail!("pull request #{} has no commits to pick", patch_from);
+            }
 
-    // 7. Try git am --3way localPath.
-    git.am(patch_path).await.with_context(|| {
-        format!(
-            "apply #{} on top of target branch {}",
-            pull_number, target_branch
-        )
-    })?;
+            // 7. git cherry-pick the commits.
+            let options = CherryPickOptions {
+                record_origin: true,
+                ..Default::default()
+            };
+            git.cherry_pick(&commits, &options).await.with_context(|| {
+                format!(
+                    "cherry-pick #{} on top of target branch {}",
+                    patch_from, target_branch
+                )
+            })?;
+        }
+    }
 
-    // 8. push
-    git.push("origin".to_string(), local_branch, true)
-        .await
-        .context("push to github")?;
-    
-    // 9. create pr
+    Ok((git, local_branch))
+}
+
+/// Writes the commits of the merged `pull_number` as a patch to `path`, fetching them
+/// from the upstream repo.
+async fn format_pull_patch(
+    git: &mut Git,
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let range = fetch_pull_commits(git, owner, repo, pull_number).await?;
+    git.format_patch(range, path).await
+}
+
+/// Fetches the commits of the merged `pull_number` from the upstream repo, returning their
+/// range. A shallow clone gets the parent of each of them too, which both `am` and
+/// `cherry-pick` apply them against, but not the older history.
+async fn fetch_pull_commits(
+    git: &mut Git,
+    owner: &str,
+    repo: &str,
+    pull_number: u64,
+) -> anyhow::Result<String> {
+    let info = pull_info(owner, repo, pull_number).await?;
+    let merge_commit = info
+        .merge_commit
+        .with_context(|| format!("pull request #{} has no merge commit", pull_number))?;
+    let url = endpoint().clone_url(owner, repo);
+    let remote_head = format!("refs/pull/{}/head", pull_number);
+    // Private to the workspace, a worktree of the mirror shares the other refs with the
+    // concurrent picks.
+    let head = format!("refs/worktree/pull/{}/head", pull_number);
+    // The oldest commit of the PR is `commit_count` commits away from its head.
+    let depth = git.depth().map(|_| info.commit_count as u32 + 1);
+    git.fetch(
+        &url,
+        &[merge_commit.clone(), format!("+{}:{}", remote_head, head)],
+        depth,
+    )
+    .await
+    .context("fetch merge commit and pull request head")?;
 
-    todo!()
+    // The commits of the PR not already on the base branch before it was merged, whether
+    // it was merged with a merge commit, squashed or rebased.
+    Ok(format!("{}^1..{}", merge_commit, head))
 }
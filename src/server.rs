@@ -0,0 +1,242 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use actions::RepoRef;
+use anyhow::{Context, Result};
+use hyper::{
+    body::HttpBody,
+    header::CONTENT_LENGTH,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use ring::hmac;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::Instrument;
+
+use crate::dispatch;
+
+// GitHub caps the payloads of webhook deliveries at 25 MB.
+const MAX_PAYLOAD_SIZE: usize = 25 * 1024 * 1024;
+
+/// A webhook delivery waiting to be handled.
+struct Job {
+    delivery: String,
+    event_name: String,
+    repo: RepoRef,
+    payload: serde_json::Value,
+}
+
+/// Serves webhooks on `addr`, queueing at most `queue_size` deliveries.
+///
+/// Deliveries are handled one at a time, so that they never share a clone.
+pub async fn serve(addr: SocketAddr, secret: String, queue_size: usize) -> Result<()> {
+    let (tx, rx) = mpsc::channel(queue_size);
+    tokio::spawn(work(rx));
+
+    let key = Arc::new(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()));
+    let make_service = make_service_fn(move |_| {
+        let key = key.clone();
+        let tx = tx.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let key = key.clone();
+                let tx = tx.clone();
+                async move {
+                    let (status, message) = accept(req, &key, &tx).await;
+                    let mut resp = Response::new(Body::from(message));
+                    *resp.status_mut() = status;
+                    Ok::<_, Infallible>(resp)
+                }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("bind {}", addr))?
+        .serve(make_service);
+    tracing::info!("listening on {}", addr);
+    server.await.context("serve webhooks")
+}
+
+async fn work(mut rx: mpsc::Receiver<Job>) {
+    while let Some(job) = rx.recv().await {
+        let span = tracing::info_span!("delivery", id = %job.delivery, event = %job.event_name);
+        // A panic only fails the delivery that caused it.
+        let handled = tokio::spawn(
+            async move { dispatch(&job.event_name, job.repo, job.payload).await }
+                .instrument(span.clone()),
+        )
+        .await;
+        let _enter = span.enter();
+        match handled {
+            Ok(Ok(())) => tracing::info!("handled delivery"),
+            Ok(Err(err)) => tracing::error!("failed to handle delivery: {:#}", err),
+            Err(err) => tracing::error!("failed to handle delivery: {}", err),
+        }
+    }
+}
+
+/// Verifies and queues the delivery in `req`, returning the response status and message.
+async fn accept(
+    req: Request<Body>,
+    key: &hmac::Key,
+    tx: &mpsc::Sender<Job>,
+) -> (StatusCode, String) {
+    if req.method() != Method::POST {
+        return (StatusCode::METHOD_NOT_ALLOWED, "expected POST".to_string());
+    }
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let (event_name, signature) = match (header("x-github-event"), header("x-hub-signature-256")) {
+        (Some(event_name), Some(signature)) => (event_name, signature),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "missing X-GitHub-Event or X-Hub-Signature-256 header".to_string(),
+            )
+        }
+    };
+    let delivery = header("x-github-delivery").unwrap_or_default();
+
+    // The body is read before it can be authenticated, so only up to what GitHub sends.
+    let too_large = header(CONTENT_LENGTH.as_str())
+        .and_then(|x| x.parse::<u64>().ok())
+        .map(|length| length > MAX_PAYLOAD_SIZE as u64);
+    if too_large == Some(true) {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload too large".to_string(),
+        );
+    }
+    let body = match read_body(req.into_body(), MAX_PAYLOAD_SIZE).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    if !verify_signature(key, &body, &signature) {
+        return (StatusCode::UNAUTHORIZED, "invalid signature".to_string());
+    }
+    if event_name == "ping" {
+        return (StatusCode::OK, "pong".to_string());
+    }
+
+    let value: serde_json::Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(err) => return (StatusCode::BAD_REQUEST, format!("parse payload: {}", err)),
+    };
+    let repo = match value
+        .pointer("/repository/full_name")
+        .and_then(|x| x.as_str())
+        .and_then(|x| x.split_once('/'))
+    {
+        Some((owner, repo)) => RepoRef {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        },
+        // Events outside of repositories, e.g. installations, are of no interest.
+        None => return (StatusCode::OK, "ignored".to_string()),
+    };
+    // Decoded by the worker, as octocrab drops fields of the payloads it models.
+    let job = Job {
+        delivery,
+        event_name,
+        repo,
+        payload: value,
+    };
+    match tx.try_send(job) {
+        Ok(()) => (StatusCode::ACCEPTED, "queued".to_string()),
+        Err(TrySendError::Full(job)) => {
+            tracing::warn!(id = %job.delivery, "dropped delivery, the queue is full");
+            (StatusCode::SERVICE_UNAVAILABLE, "queue is full".to_string())
+        }
+        Err(TrySendError::Closed(_)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "worker stopped".to_string(),
+        ),
+    }
+}
+
+/// Reads `body`, failing once more than `limit` bytes were sent whatever the
+/// `Content-Length` said.
+async fn read_body(mut body: Body, limit: usize) -> Result<Vec<u8>, (StatusCode, String)> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk =
+            chunk.map_err(|err| (StatusCode::BAD_REQUEST, format!("read body: {}", err)))?;
+        if bytes.len() + chunk.len() > limit {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload too large".to_string(),
+            ));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Verifies the `X-Hub-Signature-256` header of a delivery, in constant time.
+fn verify_signature(key: &hmac::Key, body: &[u8], signature: &str) -> bool {
+    match signature.strip_prefix("sha256=").and_then(decode_hex) {
+        Some(tag) => hmac::verify(key, body, &tag).is_ok(),
+        None => false,
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_signature() {
+        // The example from the GitHub documentation on validating webhook deliveries.
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"It's a Secret to Everybody");
+        let body = b"Hello, World!";
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify_signature(&key, body, signature));
+
+        let cases = vec![
+            "",
+            "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+            "sha1=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e18",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e1",
+            "sha256=+57107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+        ];
+        for signature in cases {
+            assert!(
+                !verify_signature(&key, body, signature),
+                "signature: {}",
+                signature
+            );
+        }
+        assert!(!verify_signature(&key, b"Hello, World?", signature));
+    }
+
+    #[tokio::test]
+    async fn test_read_body() {
+        assert_eq!(
+            read_body(Body::from("Hello, World!"), 13).await,
+            Ok(b"Hello, World!".to_vec())
+        );
+        assert_eq!(read_body(Body::empty(), 0).await, Ok(Vec::new()));
+        assert_eq!(
+            read_body(Body::from("Hello, World!"), 12).await,
+            Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload too large".to_string()
+            ))
+        );
+    }
+}
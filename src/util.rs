@@ -1,23 +1,7 @@
 use itertools::Itertools;
-use lazy_static::lazy_static;
-use regex::{Regex, RegexBuilder};
 
 use crate::config::config;
 
-pub fn match_cherry_pick_command(text: impl AsRef<str>) -> Vec<String> {
-    lazy_static! {
-        static ref RE: Regex = RegexBuilder::new(r"^(?:/cherrypick|/cherry-pick)\s+(.+)$")
-            .multi_line(true)
-            .build()
-            .unwrap();
-    }
-
-    RE.captures_iter(text.as_ref())
-        .filter_map(|caps| caps.get(1).map(|x| x.as_str().trim().to_string()))
-        .unique()
-        .collect()
-}
-
 pub fn match_label(text: impl AsRef<str>) -> Option<String> {
     let label_prefix = &config().label_prefix;
     if label_prefix.is_empty() {
@@ -28,15 +12,34 @@ pub fn match_label(text: impl AsRef<str>) -> Option<String> {
         .map(|x| x.trim().to_string())
 }
 
-pub fn is_cherry_pick_invite_command(text: impl AsRef<str>) -> bool {
-    lazy_static! {
-        static ref RE: Regex = RegexBuilder::new(r"^(?:/cherrypick|/cherry-pick)-invite\b")
-            .multi_line(true)
-            .build()
-            .unwrap();
+pub fn match_picked_label(text: impl AsRef<str>) -> Option<String> {
+    let picked_label_prefix = &config().picked_label_prefix;
+    if picked_label_prefix.is_empty() {
+        return None;
     }
+    text.as_ref()
+        .strip_prefix(picked_label_prefix)
+        .map(|x| x.trim().to_string())
+}
 
-    return RE.is_match(text.as_ref());
+/// Splits the target branches requested by `labels` and not picked yet into the ones
+/// whose pick failed and the pending ones.
+pub fn pending_targets(labels: &[String]) -> (Vec<String>, Vec<String>) {
+    let failed_label_prefix = &config().failed_label_prefix;
+    labels
+        .iter()
+        .filter_map(match_label)
+        .unique()
+        .filter(|target| {
+            !labels
+                .iter()
+                .any(|label| match_picked_label(label).as_deref() == Some(target.as_str()))
+        })
+        .partition(|target| {
+            labels
+                .iter()
+                .any(|label| label.strip_prefix(failed_label_prefix) == Some(target.as_str()))
+        })
 }
 
 #[cfg(test)]
@@ -44,35 +47,10 @@ mod tests {
 
     use pretty_assertions::assert_eq;
 
-    use crate::config::{replace_config, Config};
+    use crate::config::{lock_config, replace_config, Config};
 
     use super::*;
 
-    #[test]
-    fn test_match_cherry_pick_command() {
-        let cases = vec![
-            ("/cherrypick xx", vec!["xx"]),
-            ("/cherry-pick xx", vec!["xx"]),
-            ("/cherrypickxxx", vec![]),
-            ("/cherry-pickxxx", vec![]),
-            (
-                r#"
-/cherry-pick r
-xxxx
-/cherry-pick    releasev0.3
-/cherrypick releasev0.3
-/cherrypick release/v0.5
-/cherrypick release/v0.5😊
-        "#,
-                vec!["r", "releasev0.3", "release/v0.5", "release/v0.5😊"],
-            ),
-        ];
-
-        for (cmd, expected) in cases {
-            assert_eq!(match_cherry_pick_command(cmd), expected, "cmd: {}", cmd);
-        }
-    }
-
     #[test]
     fn test_match_label() {
         let cases = vec![
@@ -87,8 +65,9 @@ xxxx
             ("", "needs-cherry-pick-lbw", None),
         ];
 
+        let _lock = lock_config();
         for (label_prefix, label, expected) in cases {
-            let mut c = Config::from_actions();
+            let mut c = Config::from_actions().unwrap();
             c.label_prefix = label_prefix.to_string();
             replace_config(c);
 
@@ -101,22 +80,4 @@ xxxx
             );
         }
     }
-
-    #[test]
-    fn test_is_cherry_pick_invite_command() {
-        let cases = vec![
-            ("lbwnb", false),
-            ("/cherrypick-", false),
-            ("/cherry-pick", false),
-            ("/cherrypick-invite", true),
-            ("/cherry-pick-invite", true),
-            ("/cherrypick-invitexx", false),
-            ("/cherrypick-invite lbw", true),
-            ("/cherrypick-invite_lbw", false),
-        ];
-
-        for (cmd, expected) in cases {
-            assert_eq!(is_cherry_pick_invite_command(cmd), expected, "cmd: {}", cmd);
-        }
-    }
 }